@@ -0,0 +1,35 @@
+//! Public key files given with `-p`, including the namespaces recorded in
+//! their untrusted comment.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rsign2::minisign::PublicKeyBox;
+use rsign2::namespace::{key_namespaces, NamespacePolicy};
+use rsign2::platform::{with_platform, MemoryPlatform};
+use rsign2::PublicKey;
+
+const TRUSTED_COMMENT: &str = "timestamp:1556193335\tfile:test\tnamespace:release";
+
+fuzz_target!(|data: &[u8]| {
+    let platform = MemoryPlatform::new(1556193335);
+    platform.write_file("/rsign.pub", data);
+    with_platform(platform, || {
+        let namespaces = key_namespaces("/rsign.pub");
+        let s = match std::str::from_utf8(data) {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+        let pk = match PublicKeyBox::from_string(s).and_then(PublicKey::from_box) {
+            Ok(pk) => pk,
+            Err(_) => return,
+        };
+        if let Ok(Some(allowed)) = namespaces {
+            let policy = NamespacePolicy {
+                allowed_by_key: vec![(pk.clone(), allowed)],
+                ..Default::default()
+            };
+            let _ = policy.check(&pk, TRUSTED_COMMENT);
+        }
+    });
+});
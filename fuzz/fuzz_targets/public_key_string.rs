@@ -0,0 +1,19 @@
+//! Public keys given on the command line with `-P`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rsign2::raw::key_id;
+use rsign2::PublicKey;
+
+fuzz_target!(|data: &[u8]| {
+    let s = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    if let Ok(pk) = PublicKey::from_base64(s) {
+        let _ = key_id(pk.keynum());
+        let pk2 = PublicKey::from_base64(&pk.to_base64()).expect("re-encoded public key");
+        assert_eq!(pk.to_bytes(), pk2.to_bytes());
+    }
+});
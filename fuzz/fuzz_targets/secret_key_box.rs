@@ -0,0 +1,24 @@
+//! Secret key files given with `-s`. Encrypted keys are only parsed, since
+//! deriving their key would spend most of the time in scrypt.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rsign2::minisign::SecretKeyBox;
+use rsign2::PublicKey;
+
+fuzz_target!(|data: &[u8]| {
+    let s = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let sk_box = SecretKeyBox::from_string(s);
+    let sk = match sk_box.and_then(SecretKeyBox::into_unencrypted_secret_key) {
+        Ok(sk) => sk,
+        Err(_) => return,
+    };
+    let _ = PublicKey::from_secret_key(&sk);
+    if let Ok(sk_box) = sk.to_box(None) {
+        let _ = sk_box.into_string();
+    }
+});
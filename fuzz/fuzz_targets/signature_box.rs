@@ -0,0 +1,68 @@
+//! Signature files, which `verify` reads before it knows whether they were
+//! made by a trusted key. Trusted comments are printed after verification,
+//! so they must never contain unprintable characters by then, even with
+//! `--escape-comment`.
+
+#![no_main]
+
+use std::path::Path;
+
+use libfuzzer_sys::fuzz_target;
+use rsign2::bundle::SignatureBundle;
+use rsign2::certificate::{Certificate, CERT_FIELD};
+use rsign2::comment;
+use rsign2::expiry::check_expiry;
+use rsign2::helpers::is_printable;
+use rsign2::platform::{with_platform, MemoryPlatform};
+use rsign2::{cmd_verify, DataSource, PublicKey, VerifyOptions};
+
+/// The public key of the minisign test vectors, in `tests/fixtures/minisign`.
+const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+
+const NOW: u64 = 1556193335;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Ok(bundle) = SignatureBundle::from_string(s) {
+            for signature_box in bundle.boxes() {
+                let _ = signature_box.untrusted_comment();
+                let trusted_comment = match signature_box.trusted_comment() {
+                    Ok(trusted_comment) => trusted_comment,
+                    Err(_) => continue,
+                };
+                let _ = is_printable(&trusted_comment);
+                assert!(is_printable(&comment::escape(&trusted_comment)));
+                let _ = comment::fields(&trusted_comment).count();
+                let _ = check_expiry(&trusted_comment, NOW);
+                if let Some(encoded) = comment::field(&trusted_comment, CERT_FIELD) {
+                    let _ = Certificate::decode(encoded);
+                }
+            }
+        }
+    }
+
+    let pk = PublicKey::from_base64(PUBLIC_KEY).unwrap();
+    let platform = MemoryPlatform::new(NOW);
+    platform.write_file("/test", "test");
+    platform.write_file("/test.minisig", data);
+    for escape_comment in [false, true] {
+        let report = with_platform(platform.clone(), || {
+            cmd_verify(
+                std::slice::from_ref(&pk),
+                DataSource::File(Path::new("/test")),
+                "/test.minisig",
+                &VerifyOptions {
+                    allow_legacy: true,
+                    escape_comment,
+                    ..Default::default()
+                },
+                None,
+            )
+        });
+        if let Ok(report) = report {
+            for signature in &report.signatures {
+                assert!(is_printable(&signature.trusted_comment));
+            }
+        }
+    }
+});
@@ -1,362 +1,885 @@
 #[macro_use]
 extern crate clap;
 
-mod helpers;
 mod parse_args;
 
-use std::io::Write;
+use std::ffi::OsString;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
-#[cfg(any(windows, unix))]
-use dirs::home_dir;
 use minisign::*;
-
-use crate::helpers::{
-    create_dir, create_file, create_sig_file, is_printable, open_data_file, unix_timestamp,
+use rsign2::bundle::SignatureBundle;
+use rsign2::certificate::{cmd_certify, Certificate, Constraints, CERT_SUFFIX};
+use rsign2::comment::{self, Sanitize};
+use rsign2::compat::{
+    cmd_change_password, cmd_recreate_pk, cmd_sign_files, minisign_sk_path, MINISIGN_PKFILE,
+};
+use rsign2::config::{
+    cmd_config_show, cmd_migrate, create_sk_path_or_default, get_pk_path, get_sk_path,
+    project_root, Config,
+};
+use rsign2::expiry::{parse_expiry, parse_time};
+use rsign2::filename::FileBinding;
+use rsign2::generate::{cmd_generate, GenerateOptions};
+use rsign2::helpers::{create_file, unix_timestamp, with_suffix, Passphrase};
+use rsign2::keyformats::{
+    cmd_export_pk, cmd_import_key, cmd_import_pk, PublicKeyFormat, SecretKeyFormat,
+};
+use rsign2::namespace::{key_namespaces, NamespacePolicy};
+use rsign2::policy::Policy;
+use rsign2::prehash::{cmd_hash, decode_prehash, DataSource};
+use rsign2::raw::key_id;
+use rsign2::sigfile::{cmd_sig_check, cmd_sig_normalize, cmd_sig_set_untrusted_comment};
+use rsign2::sign::{cmd_countersign, cmd_sign, SignOptions, COUNTERSIG_SUFFIX};
+use rsign2::signify::{cmd_convert, cmd_verify_signify, Format, SIGNIFY_SIG_SUFFIX};
+use rsign2::sshsig::{
+    cmd_ssh_check_novalidate, cmd_ssh_find_principals, cmd_ssh_public_key, cmd_ssh_sign,
+    cmd_ssh_verify, parse_ssh_time, SshKeygenOp, SshKeygenOptions,
+};
+use rsign2::state::{cmd_state_reset, cmd_state_show, VerifierState};
+use rsign2::verify::{
+    cmd_verify, cmd_verify_countersignatures, VerificationReport, VerifiedSignature, VerifyOptions,
 };
-use crate::parse_args::*;
-
-#[cfg(not(any(windows, unix)))]
-fn home_dir() -> Option<PathBuf> {
-    Some(PathBuf::from("."))
-}
 
-pub fn cmd_generate<P, Q>(
-    force: bool,
-    pk_path: P,
-    sk_path: Q,
-    comment: Option<&str>,
-    passwordless: bool,
-    unencrypted: bool,
-) -> Result<KeyPair>
-where
-    P: AsRef<Path>,
-    Q: AsRef<Path>,
-{
-    let pk_path = pk_path.as_ref();
-    let sk_path = sk_path.as_ref();
-    if pk_path.exists() {
-        if !force {
-            return Err(PError::new(
-                ErrorKind::Io,
-                format!(
-                    "Key generation aborted:\n
-{} already exists\n
-If you really want to overwrite the existing key pair, add the -f switch to\n
-force this operation.",
-                    pk_path.display()
-                ),
-            ));
-        } else {
-            std::fs::remove_file(pk_path)?;
-        }
-    }
-    let mut pk_writer = create_file(pk_path, 0o644)?;
-    let mut sk_writer = create_file(sk_path, 0o600)?;
-    let kp = if unencrypted {
-        let kp = KeyPair::generate_unencrypted_keypair()?;
-        pk_writer.write_all(&kp.pk.to_box()?.to_bytes())?;
-        sk_writer.write_all(&kp.sk.to_box(comment)?.to_bytes())?;
-        kp
-    } else {
-        KeyPair::generate_and_write_encrypted_keypair(
-            &mut pk_writer,
-            &mut sk_writer,
-            comment,
-            if passwordless {
-                Some(Default::default())
-            } else {
-                None
-            },
-        )?
-    };
-    pk_writer.flush()?;
-    sk_writer.flush()?;
-    Ok(kp)
-}
+use crate::parse_args::*;
 
-pub fn cmd_sign<P, Q, R>(
-    pk: Option<PublicKey>,
-    sk_path: P,
-    signature_path: Q,
-    data_path: R,
-    trusted_comment: Option<&str>,
-    untrusted_comment: Option<&str>,
-    passwordless: bool,
-) -> Result<()>
-where
-    P: AsRef<Path>,
-    Q: AsRef<Path>,
-    R: AsRef<Path>,
-{
-    if !sk_path.as_ref().exists() {
-        return Err(PError::new(
-            ErrorKind::Io,
-            format!(
-                "can't find secret key file at {}, try using -s",
-                sk_path.as_ref().display()
-            ),
-        ));
+/// Copy the verified data to the standard output.
+fn output_data(report: &VerificationReport) -> Result<()> {
+    if let Some(data) = &report.data {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(data)?;
+        stdout.flush()?;
     }
-    let mut signature_box_writer = create_sig_file(&signature_path)?;
-    let sk_str = std::fs::read_to_string(sk_path)?;
-    let sk_box: SecretKeyBox = sk_str.clone().into();
-    let sk = match sk_box.into_unencrypted_secret_key() {
-        Ok(sk) => sk,
-        Err(_) => {
-            let sk_box: SecretKeyBox = sk_str.into();
-            sk_box.into_secret_key(if passwordless {
-                Some(Default::default())
-            } else {
-                None
-            })?
-        }
-    };
-    let trusted_comment = if let Some(trusted_comment) = trusted_comment {
-        trusted_comment.to_string()
-    } else {
-        format!(
-            "timestamp:{}\tfile:{}\tprehashed",
-            unix_timestamp(),
-            data_path.as_ref().display()
-        )
-    };
-    let data_reader = open_data_file(data_path)?;
-    let signature_box = sign(
-        pk.as_ref(),
-        &sk,
-        data_reader,
-        Some(trusted_comment.as_str()),
-        untrusted_comment,
-    )?;
-    signature_box_writer.write_all(&signature_box.to_bytes())?;
-    signature_box_writer.flush()?;
     Ok(())
 }
 
-pub fn cmd_verify<P, Q>(
-    pk: PublicKey,
-    data_path: P,
-    signature_path: Q,
-    quiet: bool,
-    output: bool,
-    allow_legacy: bool,
-) -> Result<()>
-where
-    P: AsRef<Path>,
-    Q: AsRef<Path>,
-{
-    let signature_box = SignatureBox::from_file(&signature_path).map_err(|err| {
-        PError::new(
-            ErrorKind::Io,
-            format!(
-                "could not read signature file {}: {}",
-                signature_path.as_ref().display(),
-                err
-            ),
-        )
-    })?;
-    let data_reader = open_data_file(&data_path).map_err(|err| {
-        PError::new(
-            ErrorKind::Io,
-            format!(
-                "could not read data file {}: {}",
-                data_path.as_ref().display(),
-                err
-            ),
-        )
-    })?;
-    let trusted_comment = signature_box.trusted_comment()?;
-    if !is_printable(&trusted_comment) {
-        return Err(PError::new(
-            ErrorKind::Verify,
-            "Signature file contains unprintable characters",
-        ));
-    }
-
-    verify(&pk, &signature_box, data_reader, true, output, allow_legacy)?;
-
-    if !quiet {
-        eprintln!("Signature and comment signature verified");
-        eprintln!("Trusted comment: {}", trusted_comment);
+fn report_countersignatures(countersignatures: &[VerifiedSignature]) {
+    for countersignature in countersignatures {
+        eprintln!(
+            "Countersignature by key {} verified",
+            countersignature.key_id
+        );
+        eprintln!(
+            "Countersignature trusted comment: {}",
+            countersignature.trusted_comment
+        );
     }
-    Ok(())
 }
 
-fn create_sk_path_or_default(sk_path_str: Option<&str>, force: bool) -> Result<PathBuf> {
-    let sk_path = match sk_path_str {
-        Some(path) => {
-            let complete_path = PathBuf::from(path);
-            let mut dir = complete_path.clone();
-            dir.pop();
-            create_dir(&dir)?;
-            complete_path
-        }
-        None => match std::env::var(SIG_DEFAULT_CONFIG_DIR_ENV_VAR) {
-            Ok(env_path) => {
-                let mut complete_path = PathBuf::from(env_path);
-                if !complete_path.exists() {
-                    return Err(PError::new(
-                        ErrorKind::Io,
-                        format!(
-                            "folder {} referenced by {} doesn't exist, you'll have to create \
-                             it yourself",
-                            complete_path.display(),
-                            SIG_DEFAULT_CONFIG_DIR_ENV_VAR
-                        ),
-                    ));
-                }
-                complete_path.push(SIG_DEFAULT_SKFILE);
-                complete_path
-            }
-            Err(_) => {
-                let home_path =
-                    home_dir().ok_or_else(|| PError::new(ErrorKind::Io, "can't find home dir"))?;
-                let mut complete_path = home_path;
-                complete_path.push(SIG_DEFAULT_CONFIG_DIR);
-                if !complete_path.exists() {
-                    create_dir(&complete_path)?;
-                }
-                complete_path.push(SIG_DEFAULT_SKFILE);
-                complete_path
-            }
-        },
+/// With `comment_only`, only the comment signatures were verified. The
+/// report goes to the standard error, or to the standard output with
+/// `to_stdout`, like minisign does when the data isn't written there.
+fn report_verified(
+    report: &VerificationReport,
+    comment_only: bool,
+    to_stdout: bool,
+) -> io::Result<()> {
+    let mut out: Box<dyn Write> = if to_stdout {
+        Box::new(io::stdout())
+    } else {
+        Box::new(io::stderr())
     };
-    if sk_path.exists() {
-        if !force {
-            return Err(PError::new(
-                ErrorKind::Io,
-                format!(
-                    "Key generation aborted:
-{} already exists
-
-If you really want to overwrite the existing key pair, add the -f switch to
-force this operation.",
-                    sk_path.display()
-                ),
-            ));
-        } else {
-            std::fs::remove_file(&sk_path)?;
+    let verified = if comment_only {
+        "Comment signature"
+    } else {
+        "Signature and comment signature"
+    };
+    if let [signature] = report.signatures.as_slice() {
+        writeln!(out, "{verified} verified")?;
+        writeln!(out, "Trusted comment: {}", signature.trusted_comment)?;
+    } else {
+        for signature in &report.signatures {
+            writeln!(out, "{verified} by key {} verified", signature.key_id)?;
+            writeln!(out, "Trusted comment: {}", signature.trusted_comment)?;
         }
+        writeln!(
+            out,
+            "{} of {} trusted keys signed (threshold: {})",
+            report.signatures.len(),
+            report.trusted_keys,
+            report.threshold
+        )?;
     }
-    Ok(sk_path)
-}
-
-fn get_pk_path(explicit_path: Option<&str>) -> Result<PathBuf> {
-    Ok(PathBuf::from(explicit_path.unwrap_or(SIG_DEFAULT_PKFILE)))
+    out.flush()
 }
 
-fn get_sk_path(explicit_path: Option<&str>) -> Result<PathBuf> {
-    let default_file_name = SIG_DEFAULT_SKFILE;
-    match explicit_path {
-        Some(explicit_path) => Ok(PathBuf::from(explicit_path)),
-        None => match std::env::var(SIG_DEFAULT_CONFIG_DIR_ENV_VAR) {
-            Ok(env_path) => {
-                let mut complete_path = PathBuf::from(env_path);
-                complete_path.push(default_file_name);
-                Ok(complete_path)
-            }
-            Err(_) => {
-                let home_path =
-                    home_dir().ok_or_else(|| PError::new(ErrorKind::Io, "can't find home dir"))?;
-                let mut complete_path = home_path;
-                complete_path.push(SIG_DEFAULT_CONFIG_DIR);
-                complete_path.push(default_file_name);
-                Ok(complete_path)
-            }
-        },
-    }
+fn report_generated(sk_path: &Path, pk_path: &Path, pk: &PublicKey, verify_command: &str) {
+    println!(
+        "\nThe secret key was saved as {} - Keep it secret!",
+        sk_path.display()
+    );
+    println!(
+        "The public key was saved as {} - That one can be public.\n",
+        pk_path.display()
+    );
+    println!("Files signed using this key pair can be verified with the following command:\n");
+    println!("{} {}", verify_command, pk.to_base64());
 }
 
 fn run(args: clap::ArgMatches, help_usage: &str) -> Result<()> {
+    let config = Config::load()?;
     if let Some(generate_action) = args.subcommand_matches("generate") {
         let force = generate_action.get_flag("force");
         let pk_path = get_pk_path(
             generate_action
-                .get_one::<String>("pk_path")
-                .map(|s| s.as_str()),
+                .get_one::<PathBuf>("pk_path")
+                .map(PathBuf::as_path),
+            &config,
         )?;
-        let sk_path_str = generate_action.get_one::<String>("sk_path");
-        let sk_path = create_sk_path_or_default(sk_path_str.map(|s| s.as_str()), force)?;
+        let sk_path = generate_action
+            .get_one::<PathBuf>("sk_path")
+            .map(PathBuf::from)
+            .or_else(|| config.secret_key.clone());
+        let sk_path = create_sk_path_or_default(sk_path, force)?;
         let comment = generate_action.get_one::<String>("comment");
         let passwordless = generate_action.get_flag("passwordless");
         let unencrypted = generate_action.get_flag("unencrypted");
-        let KeyPair { pk, .. } = cmd_generate(
-            force,
+        let namespaces = generate_action.get_one::<String>("namespaces");
+        let generated = cmd_generate(
             &pk_path,
             &sk_path,
-            comment.map(|s| s.as_str()),
-            passwordless,
-            unencrypted,
+            &GenerateOptions {
+                force,
+                comment: comment.map(|s| s.as_str()),
+                passwordless,
+                unencrypted,
+                namespaces: namespaces.map(|s| s.as_str()),
+            },
         )?;
-        println!(
-            "\nThe secret key was saved as {} - Keep it secret!",
-            sk_path.display()
-        );
-        println!(
-            "The public key was saved as {} - That one can be public.\n",
-            pk_path.display()
+        report_generated(
+            &sk_path,
+            &pk_path,
+            &generated.key_pair.pk,
+            "rsign verify <file> -P",
         );
-        println!("Files signed using this key pair can be verified with the following command:\n");
-        println!("rsign verify <file> -P {}", pk.to_base64());
         Ok(())
     } else if let Some(sign_action) = args.subcommand_matches("sign") {
-        let sk_path = get_sk_path(sign_action.get_one::<String>("sk_path").map(|s| s.as_str()))?;
+        let sk_path = get_sk_path(
+            sign_action
+                .get_one::<PathBuf>("sk_path")
+                .map(PathBuf::as_path),
+            &config,
+        )?;
         let pk = if let Some(pk_inline) = sign_action.get_one::<String>("public_key") {
             Some(PublicKey::from_base64(pk_inline)?)
-        } else if let Some(pk_path) = sign_action.get_one::<String>("pk_path") {
-            Some(PublicKey::from_file(get_pk_path(Some(pk_path))?)?)
+        } else if let Some(pk_path) = sign_action.get_one::<PathBuf>("pk_path") {
+            Some(PublicKey::from_file(get_pk_path(
+                Some(pk_path.as_path()),
+                &config,
+            )?)?)
         } else {
             None
         };
-        let data_path = PathBuf::from(sign_action.get_one::<String>("data").unwrap()); // safe to unwrap
-        let signature_path = if let Some(file) = sign_action.get_one::<String>("sig_file") {
+        let prehash = match sign_action.get_one::<String>("prehash") {
+            Some(prehash) => Some(decode_prehash(prehash)?),
+            None => None,
+        };
+        let data_path = sign_action.get_one::<PathBuf>("data").map(PathBuf::from);
+        let signature_path = if let Some(file) = sign_action.get_one::<PathBuf>("sig_file") {
             PathBuf::from(file)
         } else {
-            PathBuf::from(format!("{}{}", data_path.display(), SIG_SUFFIX))
+            let data_path = data_path.as_ref().unwrap(); // safe to unwrap
+            with_suffix(data_path, config.sig_suffix())
+        };
+        let data = match (&prehash, &data_path) {
+            (Some(prehash), _) => DataSource::Prehash(prehash),
+            (None, Some(data_path)) => DataSource::File(data_path),
+            (None, None) => unreachable!(),
         };
-        let trusted_comment = sign_action.get_one::<String>("trusted-comment");
-        let trusted_comment = trusted_comment.map(|s| s.as_str());
+        let trusted_comment = sign_action
+            .get_one::<String>("trusted-comment")
+            .cloned()
+            .or_else(|| config.trusted_comment(unix_timestamp(), data_path.as_deref()));
+        let trusted_comment = trusted_comment.as_deref();
         let untrusted_comment = sign_action.get_one::<String>("untrusted-comment");
         let untrusted_comment = untrusted_comment.map(|s| s.as_str());
-        let passwordless = sign_action.get_flag("passwordless");
+        let passphrase = config.passphrase(sign_action.get_flag("passwordless"));
+        let append = sign_action.get_flag("append");
+        let expires = sign_action
+            .get_one::<String>("expires")
+            .map(|s| parse_expiry(s, unix_timestamp()))
+            .transpose()?;
+        let certificate = sign_action
+            .get_one::<PathBuf>("certificate")
+            .map(Certificate::from_file)
+            .transpose()?;
         cmd_sign(
-            pk,
             sk_path,
             signature_path,
-            &data_path,
-            trusted_comment,
-            untrusted_comment,
-            passwordless,
-        )
+            data,
+            &SignOptions {
+                pk: pk.as_ref(),
+                trusted_comment,
+                untrusted_comment,
+                passphrase,
+                append,
+                certificate: certificate.as_ref(),
+                namespace: sign_action
+                    .get_one::<String>("namespace")
+                    .map(|s| s.as_str()),
+                expires,
+                sanitize: Sanitize::from_name(
+                    sign_action.get_one::<String>("sanitize-comment").unwrap(), // safe to unwrap
+                )?,
+            },
+        )?;
+        Ok(())
     } else if let Some(verify_action) = args.subcommand_matches("verify") {
-        let pk = if let Some(pk_inline) = verify_action.get_one::<String>("public_key") {
-            PublicKey::from_base64(pk_inline)?
+        let mut namespaces = NamespacePolicy {
+            required: verify_action.get_one::<String>("namespace").cloned(),
+            ..Default::default()
+        };
+        let pks = if let Some(pk_inlines) = verify_action.get_many::<String>("public_key") {
+            pk_inlines
+                .map(|pk_inline| PublicKey::from_base64(pk_inline))
+                .collect::<Result<Vec<_>>>()?
         } else {
-            PublicKey::from_file(get_pk_path(
-                verify_action
-                    .get_one::<String>("pk_path")
-                    .map(|s| s.as_str()),
-            )?)?
+            let pk_paths = match verify_action.get_many::<PathBuf>("pk_path") {
+                Some(pk_paths) => pk_paths
+                    .map(|pk_path| get_pk_path(Some(pk_path.as_path()), &config))
+                    .collect::<Result<Vec<_>>>()?,
+                None => vec![get_pk_path(None, &config)?],
+            };
+            let mut pks = vec![];
+            for pk_path in pk_paths {
+                let pk = PublicKey::from_file(&pk_path)?;
+                if let Some(allowed) = key_namespaces(&pk_path)? {
+                    namespaces.allowed_by_key.push((pk.clone(), allowed));
+                }
+                pks.push(pk);
+            }
+            pks
         };
-        let data_path = verify_action.get_one::<String>("file").unwrap();
-        let signature_path = if let Some(path) = verify_action.get_one::<String>("sig_file") {
+        let threshold = verify_action.get_one::<usize>("threshold").copied();
+        let certificate = verify_action
+            .get_one::<PathBuf>("certificate")
+            .map(Certificate::from_file)
+            .transpose()?;
+        let purpose = verify_action
+            .get_one::<String>("purpose")
+            .map(|s| s.as_str());
+        let at = verify_action
+            .get_one::<String>("at")
+            .map(|s| parse_time(s))
+            .transpose()?;
+        let countersigners = verify_action
+            .get_many::<PathBuf>("require_countersign")
+            .unwrap_or_default()
+            .map(PublicKey::from_file)
+            .collect::<Result<Vec<_>>>()?;
+        let prehash = match verify_action.get_one::<String>("prehash") {
+            Some(prehash) => Some(decode_prehash(prehash)?),
+            None => None,
+        };
+        let format = Format::from_name(verify_action.get_one::<String>("format").unwrap())?;
+        let sig_suffix = match format {
+            Format::Minisign => config.sig_suffix(),
+            _ => SIGNIFY_SIG_SUFFIX,
+        };
+        let comment_only = verify_action.get_flag("comment-only");
+        let file = verify_action.get_one::<PathBuf>("file").cloned();
+        // With --comment-only, the only file is the signature file
+        let data_path = file.clone().filter(|_| !comment_only);
+        let signature_path = if let Some(path) = verify_action.get_one::<PathBuf>("sig_file") {
             PathBuf::from(path)
+        } else if comment_only {
+            file.unwrap() // safe to unwrap
         } else {
-            PathBuf::from(format!("{data_path}{SIG_SUFFIX}"))
+            let data_path = data_path.as_ref().unwrap(); // safe to unwrap
+            with_suffix(data_path, sig_suffix)
+        };
+        let data = match (&prehash, &data_path) {
+            (Some(prehash), _) => DataSource::Prehash(prehash),
+            (None, Some(data_path)) => DataSource::File(data_path),
+            (None, None) => DataSource::CommentOnly,
+        };
+        let mut state = verify_action
+            .get_one::<PathBuf>("state")
+            .map(VerifierState::load)
+            .transpose()?;
+        let policy = verify_action
+            .get_one::<PathBuf>("policy")
+            .map(PathBuf::from)
+            .or_else(|| config.policy.clone())
+            .map(|path| {
+                Policy::from_file(path).map(|policy| Policy {
+                    root: project_root(),
+                    ..policy
+                })
+            })
+            .transpose()?;
+        let artifact = match (verify_action.get_one::<String>("artifact"), &data_path) {
+            (Some(artifact), _) => artifact.clone(),
+            (None, Some(data_path)) => {
+                comment::encode_path(data_path.file_name().map_or(data_path, Path::new))
+            }
+            (None, None) if state.is_some() => {
+                return Err(PError::new(
+                    ErrorKind::Misc,
+                    "--artifact is required to use a state file without a data file",
+                ))
+            }
+            (None, None) => String::new(),
         };
         let quiet = verify_action.get_flag("quiet");
         let output = verify_action.get_flag("output");
-        let allow_legacy = verify_action.get_flag("allow-legacy");
-        cmd_verify(pk, data_path, signature_path, quiet, output, allow_legacy)
+        let allow_legacy =
+            verify_action.get_flag("allow-legacy") || config.allow_legacy.unwrap_or_default();
+        let json = verify_action.get_flag("json");
+        let escape_comment = verify_action.get_flag("escape-comment");
+        let bind_filename = match verify_action.get_one::<String>("bind-filename") {
+            Some(name) => FileBinding::from_name(name)?,
+            None => config.bind_filename.unwrap_or_default(),
+        };
+        let countersignatures = if countersigners.is_empty() {
+            vec![]
+        } else {
+            let countersignature_path = match verify_action.get_one::<PathBuf>("countersig_file") {
+                Some(path) => PathBuf::from(path),
+                None => with_suffix(&signature_path, COUNTERSIG_SUFFIX),
+            };
+            cmd_verify_countersignatures(
+                &countersigners,
+                &signature_path,
+                countersignature_path,
+                escape_comment,
+            )?
+        };
+        if !quiet && !json {
+            report_countersignatures(&countersignatures);
+        }
+        match (format, data) {
+            (Format::Minisign, data) => {
+                let mut report = cmd_verify(
+                    &pks,
+                    data,
+                    signature_path,
+                    &VerifyOptions {
+                        threshold,
+                        certificate: certificate.as_ref(),
+                        purpose,
+                        namespaces,
+                        at,
+                        policy: policy.as_ref(),
+                        allow_legacy,
+                        escape_comment,
+                        bind_filename,
+                        keep_data: output,
+                    },
+                    state.as_mut().map(|state| (state, artifact.as_str())),
+                )?;
+                if output {
+                    output_data(&report)?;
+                }
+                if json {
+                    report.countersignatures = countersignatures;
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&report)
+                            .map_err(|e| PError::new(ErrorKind::Encoding, e.to_string()))?
+                    );
+                } else if !quiet {
+                    report_verified(&report, comment_only, false)?;
+                }
+                Ok(())
+            }
+            (_, DataSource::File(data_path)) => {
+                // Checks that only exist for minisign signatures can't be skipped
+                if policy.is_some() {
+                    return Err(PError::new(
+                        ErrorKind::Misc,
+                        "the configured policy can't be enforced on signify signatures",
+                    ));
+                }
+                if !namespaces.allowed_by_key.is_empty() {
+                    return Err(PError::new(
+                        ErrorKind::Misc,
+                        "key namespace restrictions can't be enforced on signify signatures",
+                    ));
+                }
+                if pks.len() != 1 {
+                    return Err(PError::new(
+                        ErrorKind::Misc,
+                        "signify signatures can only be verified with a single public key",
+                    ));
+                }
+                let pk = pks.into_iter().next().unwrap(); // safe to unwrap
+                let data = cmd_verify_signify(pk, data_path, signature_path)?;
+                if !quiet {
+                    eprintln!("Signature verified");
+                }
+                if output {
+                    let mut stdout = std::io::stdout();
+                    stdout.write_all(&data)?;
+                    stdout.flush()?;
+                }
+                Ok(())
+            }
+            (_, DataSource::Prehash(_) | DataSource::CommentOnly) => unreachable!(),
+        }
+    } else if let Some(certify_action) = args.subcommand_matches("certify") {
+        let sk_path = get_sk_path(
+            certify_action
+                .get_one::<PathBuf>("sk_path")
+                .map(PathBuf::as_path),
+            &config,
+        )?;
+        let subkey_path = certify_action.get_one::<PathBuf>("subkey").unwrap(); // safe to unwrap
+        let subkey = PublicKey::from_file(subkey_path)?;
+        let time = |name: &str| {
+            certify_action
+                .get_one::<String>(name)
+                .map(|s| parse_ssh_time(s))
+                .transpose()
+        };
+        let constraints = Constraints {
+            not_before: time("valid_from")?,
+            not_after: time("valid_until")?,
+            files: certify_action.get_one::<String>("files").cloned(),
+            purpose: certify_action.get_one::<String>("purpose").cloned(),
+        };
+        let certificate_path = match certify_action.get_one::<PathBuf>("output") {
+            Some(path) => PathBuf::from(path),
+            None => with_suffix(subkey_path, CERT_SUFFIX),
+        };
+        cmd_certify(
+            None,
+            sk_path,
+            certify_action.get_one::<PathBuf>("issuer_certificate"),
+            &subkey,
+            &constraints,
+            &certificate_path,
+            &config.passphrase(certify_action.get_flag("passwordless")),
+        )?;
+        eprintln!(
+            "The certificate for key {} was saved as {}",
+            key_id(subkey.keynum()),
+            certificate_path.display()
+        );
+        Ok(())
+    } else if let Some(countersign_action) = args.subcommand_matches("countersign") {
+        let sk_path = get_sk_path(
+            countersign_action
+                .get_one::<PathBuf>("sk_path")
+                .map(PathBuf::as_path),
+            &config,
+        )?;
+        let signature_path =
+            PathBuf::from(countersign_action.get_one::<PathBuf>("sig_file").unwrap()); // safe to unwrap
+        let countersignature_path = match countersign_action.get_one::<PathBuf>("countersig_file") {
+            Some(path) => PathBuf::from(path),
+            None => with_suffix(&signature_path, COUNTERSIG_SUFFIX),
+        };
+        cmd_countersign(
+            sk_path,
+            &signature_path,
+            &countersignature_path,
+            &SignOptions {
+                trusted_comment: countersign_action
+                    .get_one::<String>("trusted-comment")
+                    .map(|s| s.as_str()),
+                untrusted_comment: countersign_action
+                    .get_one::<String>("untrusted-comment")
+                    .map(|s| s.as_str()),
+                passphrase: config.passphrase(countersign_action.get_flag("passwordless")),
+                append: countersign_action.get_flag("append"),
+                sanitize: Sanitize::from_name(
+                    countersign_action
+                        .get_one::<String>("sanitize-comment")
+                        .unwrap(), // safe to unwrap
+                )?,
+                ..Default::default()
+            },
+        )?;
+        eprintln!(
+            "Countersignature saved as {}",
+            countersignature_path.display()
+        );
+        Ok(())
+    } else if let Some(convert_action) = args.subcommand_matches("convert") {
+        let to = Format::from_name(convert_action.get_one::<String>("to").unwrap())?;
+        let input_path = convert_action.get_one::<PathBuf>("input").unwrap(); // safe to unwrap
+        let output_path = convert_action.get_one::<PathBuf>("output").unwrap(); // safe to unwrap
+        let sk_path = convert_action
+            .get_one::<PathBuf>("sk_path")
+            .map(PathBuf::from);
+        let comment = convert_action.get_one::<String>("comment");
+        let passwordless = convert_action.get_flag("passwordless");
+        let unencrypted = convert_action.get_flag("unencrypted");
+        cmd_convert(
+            input_path,
+            output_path,
+            to,
+            sk_path.as_deref(),
+            comment.map(|s| s.as_str()),
+            &config.passphrase(passwordless),
+            passwordless,
+            unencrypted,
+        )?;
+        eprintln!(
+            "Converted {} to {}",
+            input_path.display(),
+            output_path.display()
+        );
+        Ok(())
+    } else if let Some(ssh_action) = args.subcommand_matches("ssh-keygen") {
+        let missing =
+            |flag: &str| PError::new(ErrorKind::Misc, format!("this operation requires {flag}"));
+        let required = |name: &str, flag: &str| {
+            ssh_action
+                .get_one::<String>(name)
+                .ok_or_else(|| missing(flag))
+        };
+        let required_path = |name: &str, flag: &str| {
+            ssh_action
+                .get_one::<PathBuf>(name)
+                .ok_or_else(|| missing(flag))
+        };
+        if ssh_action.get_flag("print_public_key") {
+            println!("{}", cmd_ssh_public_key(required_path("key_file", "-f")?)?);
+            return Ok(());
+        }
+        if ssh_action.get_flag("agent") {
+            return Err(PError::new(
+                ErrorKind::Misc,
+                "keys from ssh-agent are not supported, use -f with a minisign secret key",
+            ));
+        }
+        let op = SshKeygenOp::from_name(ssh_action.get_one::<String>("op").unwrap())?;
+        let options: Vec<String> = ssh_action
+            .get_many::<String>("option")
+            .unwrap_or_default()
+            .cloned()
+            .collect();
+        let options = SshKeygenOptions::parse(&options)?;
+        match op {
+            SshKeygenOp::Sign => {
+                let data_paths: Vec<PathBuf> = ssh_action
+                    .get_many::<PathBuf>("files")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect();
+                cmd_ssh_sign(
+                    required_path("key_file", "-f")?,
+                    required("namespace", "-n")?,
+                    &options,
+                    &data_paths,
+                )
+            }
+            SshKeygenOp::Verify => {
+                let message = cmd_ssh_verify(
+                    required_path("key_file", "-f")?,
+                    required("principal", "-I")?,
+                    required("namespace", "-n")?,
+                    required_path("sig_file", "-s")?,
+                    ssh_action.get_one::<PathBuf>("revocation_file"),
+                    &options,
+                )?;
+                println!("{message}");
+                Ok(())
+            }
+            SshKeygenOp::FindPrincipals => {
+                for principal in cmd_ssh_find_principals(
+                    required_path("key_file", "-f")?,
+                    required_path("sig_file", "-s")?,
+                    &options,
+                )? {
+                    println!("{principal}");
+                }
+                Ok(())
+            }
+            SshKeygenOp::CheckNovalidate => {
+                let message = cmd_ssh_check_novalidate(
+                    required("namespace", "-n")?,
+                    required_path("sig_file", "-s")?,
+                )?;
+                println!("{message}");
+                Ok(())
+            }
+        }
+    } else if let Some(export_action) = args.subcommand_matches("export-pk") {
+        let pk = if let Some(pk_inline) = export_action.get_one::<String>("public_key") {
+            PublicKey::from_base64(pk_inline)?
+        } else {
+            PublicKey::from_file(get_pk_path(
+                export_action
+                    .get_one::<PathBuf>("pk_path")
+                    .map(PathBuf::as_path),
+                &config,
+            )?)?
+        };
+        let format =
+            PublicKeyFormat::from_name(export_action.get_one::<String>("format").unwrap())?;
+        let exported = cmd_export_pk(&pk, format)?;
+        if let Some(output_path) = export_action.get_one::<PathBuf>("output") {
+            let mut writer = create_file(output_path, 0o644)?;
+            writer.write_all(&exported)?;
+            writer.flush()?;
+        } else {
+            std::io::stdout().write_all(&exported)?;
+        }
+        Ok(())
+    } else if let Some(import_action) = args.subcommand_matches("import-pk") {
+        let pk_path = get_pk_path(
+            import_action
+                .get_one::<PathBuf>("pk_path")
+                .map(PathBuf::as_path),
+            &config,
+        )?;
+        let format =
+            PublicKeyFormat::from_name(import_action.get_one::<String>("format").unwrap())?;
+        let pk = cmd_import_pk(
+            import_action.get_flag("force"),
+            import_action.get_one::<PathBuf>("input").unwrap(), // safe to unwrap
+            &pk_path,
+            format,
+            import_action
+                .get_one::<String>("key_id")
+                .map(|s| s.as_str()),
+        )?;
+        println!(
+            "The public key was saved as {} - Key ID: {}",
+            pk_path.display(),
+            key_id(pk.keynum())
+        );
+        Ok(())
+    } else if let Some(import_action) = args.subcommand_matches("import-key") {
+        let force = import_action.get_flag("force");
+        let pk_path = get_pk_path(
+            import_action
+                .get_one::<PathBuf>("pk_path")
+                .map(PathBuf::as_path),
+            &config,
+        )?;
+        let sk_path = import_action
+            .get_one::<PathBuf>("sk_path")
+            .map(PathBuf::from)
+            .or_else(|| config.secret_key.clone());
+        let sk_path = create_sk_path_or_default(sk_path, force)?;
+        let format =
+            SecretKeyFormat::from_name(import_action.get_one::<String>("format").unwrap())?;
+        let KeyPair { pk, .. } = cmd_import_key(
+            force,
+            import_action.get_one::<PathBuf>("input").unwrap(), // safe to unwrap
+            &pk_path,
+            &sk_path,
+            format,
+            import_action
+                .get_one::<String>("key_id")
+                .map(|s| s.as_str()),
+            import_action
+                .get_one::<String>("comment")
+                .map(|s| s.as_str()),
+            &config.passphrase(false),
+            import_action.get_flag("passwordless"),
+            import_action.get_flag("unencrypted"),
+        )?;
+        report_generated(&sk_path, &pk_path, &pk, "rsign verify <file> -P");
+        Ok(())
+    } else if let Some(state_action) = args.subcommand_matches("state") {
+        if let Some(show_action) = state_action.subcommand_matches("show") {
+            print!(
+                "{}",
+                cmd_state_show(show_action.get_one::<PathBuf>("state_file").unwrap())? // safe to unwrap
+            );
+        } else if let Some(reset_action) = state_action.subcommand_matches("reset") {
+            let removed = cmd_state_reset(
+                reset_action.get_one::<PathBuf>("state_file").unwrap(), // safe to unwrap
+                reset_action.get_one::<String>("key_id").map(|s| s.as_str()),
+                reset_action
+                    .get_one::<String>("artifact")
+                    .map(|s| s.as_str()),
+            )?;
+            eprintln!("Removed {removed} entries");
+        }
+        Ok(())
+    } else if let Some(sig_action) = args.subcommand_matches("sig") {
+        if let Some(check_action) = sig_action.subcommand_matches("check") {
+            let mut total = 0;
+            for sig_path in check_action.get_many::<PathBuf>("sig_files").unwrap() {
+                let issues = cmd_sig_check(sig_path)?;
+                for issue in &issues {
+                    println!("{}: {}", sig_path.display(), issue);
+                }
+                total += issues.len();
+            }
+            if total > 0 {
+                return Err(PError::new(
+                    ErrorKind::Verify,
+                    format!("{total} issues found"),
+                ));
+            }
+        } else if let Some(normalize_action) = sig_action.subcommand_matches("normalize") {
+            for sig_path in normalize_action.get_many::<PathBuf>("sig_files").unwrap() {
+                if cmd_sig_normalize(sig_path)? {
+                    eprintln!("Normalized {}", sig_path.display());
+                }
+            }
+        } else if let Some(set_action) = sig_action.subcommand_matches("set-untrusted-comment") {
+            let sig_path = set_action.get_one::<PathBuf>("sig_file").unwrap(); // safe to unwrap
+            let changed = cmd_sig_set_untrusted_comment(
+                sig_path,
+                set_action.get_one::<String>("untrusted_comment").unwrap(), // safe to unwrap
+                set_action.get_one::<String>("key_id").map(|s| s.as_str()),
+            )?;
+            eprintln!(
+                "Changed {changed} untrusted comments in {}",
+                sig_path.display()
+            );
+        }
+        Ok(())
+    } else if let Some(config_action) = args.subcommand_matches("config") {
+        if config_action.subcommand_matches("show").is_some() {
+            print!(
+                "{}",
+                cmd_config_show(
+                    &config,
+                    &get_sk_path(None, &config)?,
+                    &get_pk_path(None, &config)?
+                )
+            );
+        }
+        Ok(())
+    } else if let Some(migrate_action) = args.subcommand_matches("migrate") {
+        let dry_run = migrate_action.get_flag("dry_run");
+        for (src, dst) in cmd_migrate(dry_run)? {
+            if dry_run {
+                println!("Would move {} to {}", src.display(), dst.display());
+            } else {
+                println!("Moved {} to {}", src.display(), dst.display());
+            }
+        }
+        Ok(())
+    } else if let Some(compat_action) = args.subcommand_matches("compat") {
+        let minisign_args = compat_action
+            .get_many::<OsString>("args")
+            .unwrap_or_default()
+            .cloned();
+        run_minisign(
+            minisign_command()
+                .get_matches_from(std::iter::once(OsString::from("minisign")).chain(minisign_args)),
+        )
+    } else if let Some(hash_action) = args.subcommand_matches("hash") {
+        let data_path = hash_action.get_one::<PathBuf>("file").unwrap(); // safe to unwrap
+        let base64 = hash_action.get_flag("base64");
+        println!("{}", cmd_hash(data_path, base64)?);
+        Ok(())
     } else {
         println!("{help_usage}\n");
         std::process::exit(1);
     }
 }
 
+/// Run the minisign-compatible interface, with minisign's default paths.
+fn run_minisign(args: clap::ArgMatches) -> Result<()> {
+    if args.get_flag("version") {
+        println!("rsign {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    let sk_path = match args.get_one::<PathBuf>("sk_path") {
+        Some(path) => PathBuf::from(path),
+        None => minisign_sk_path()?,
+    };
+    let pk_path = PathBuf::from(
+        args.get_one::<PathBuf>("pk_path")
+            .map_or(Path::new(MINISIGN_PKFILE), PathBuf::as_path),
+    );
+    let passwordless = args.get_flag("passwordless");
+    let data_paths: Vec<&Path> = args
+        .get_many::<PathBuf>("message_files")
+        .unwrap_or_default()
+        .map(Path::new)
+        .collect();
+    if args.get_flag("generate") {
+        let force = args.get_flag("force");
+        let sk_path = create_sk_path_or_default(Some(sk_path), force)?;
+        let generated = cmd_generate(
+            &pk_path,
+            &sk_path,
+            &GenerateOptions {
+                force,
+                // Like minisign, -W stores the key without encryption
+                unencrypted: passwordless,
+                ..Default::default()
+            },
+        )?;
+        report_generated(
+            &sk_path,
+            &pk_path,
+            &generated.key_pair.pk,
+            "minisign -Vm <file> -P",
+        );
+    } else if args.get_flag("recreate") {
+        cmd_recreate_pk(&sk_path, &pk_path, &Passphrase::from_flag(passwordless))?;
+    } else if args.get_flag("change_password") {
+        cmd_change_password(&sk_path, passwordless)?;
+    } else if args.get_flag("sign") {
+        cmd_sign_files(
+            &sk_path,
+            &data_paths,
+            args.get_one::<PathBuf>("sig_file").map(Path::new),
+            args.get_one::<String>("trusted_comment")
+                .map(|s| s.as_str()),
+            args.get_one::<String>("untrusted_comment")
+                .map(|s| s.as_str()),
+            &Passphrase::from_flag(passwordless),
+            args.get_flag("legacy"),
+        )?;
+    } else if args.get_flag("verify") {
+        let data_path = match data_paths.as_slice() {
+            [data_path] => *data_path,
+            _ => {
+                return Err(PError::new(
+                    ErrorKind::Misc,
+                    "only one file can be verified at a time",
+                ))
+            }
+        };
+        let pk = match args.get_one::<String>("public_key") {
+            Some(pk_inline) => PublicKey::from_base64(pk_inline)?,
+            None => PublicKey::from_file(&pk_path)?,
+        };
+        let signature_path = match args.get_one::<PathBuf>("sig_file") {
+            Some(path) => PathBuf::from(path),
+            None => with_suffix(data_path, SIG_SUFFIX),
+        };
+        let pretty_quiet = args.get_flag("pretty_quiet");
+        let report = cmd_verify(
+            std::slice::from_ref(&pk),
+            DataSource::File(data_path),
+            &signature_path,
+            &VerifyOptions {
+                allow_legacy: !args.get_flag("prehashed"),
+                // Like minisign, the file name isn't checked
+                bind_filename: FileBinding::Off,
+                keep_data: args.get_flag("output"),
+                ..Default::default()
+            },
+            None,
+        )?;
+        if args.get_flag("output") {
+            output_data(&report)?;
+        }
+        if !args.get_flag("quiet") && !pretty_quiet {
+            report_verified(&report, false, !args.get_flag("output"))?;
+        }
+        if pretty_quiet {
+            let bundle = SignatureBundle::from_file(&signature_path)?;
+            if let Some(signature_box) = bundle.boxes().iter().find(|b| b.keynum() == pk.keynum()) {
+                println!("{}", signature_box.trusted_comment()?);
+            }
+        }
+    }
+    Ok(())
+}
+
 fn main() {
-    let (args, help_usage) = parse_args();
-    run(args, &help_usage).map_err(|e| e.exit()).unwrap();
+    let result = if invoked_as_minisign() {
+        run_minisign(minisign_command().get_matches())
+    } else {
+        let (args, help_usage) = parse_args();
+        run(args, &help_usage)
+    };
+    result.map_err(|e| e.exit()).unwrap();
     std::process::exit(0);
 }
@@ -1,4 +1,7 @@
-use clap::{Arg, ArgAction::SetTrue, Command};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use clap::{Arg, ArgAction::Append, ArgAction::SetTrue, ArgGroup, Command};
 
 pub fn parse_args() -> (clap::ArgMatches, String) {
     let mut app = command!()
@@ -11,6 +14,7 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("public-key-path")
                         .num_args(1)
                         .value_name("PUBLIC_KEY_PATH")
+                        .value_parser(value_parser!(PathBuf))
                         .help("path to the new public key"),
                 )
                 .arg(
@@ -19,6 +23,7 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("secret-key-path")
                         .num_args(1)
                         .value_name("SECRET_KEY_PATH")
+                        .value_parser(value_parser!(PathBuf))
                         .help("path to the new secret key"),
                 )
                 .arg(
@@ -48,6 +53,13 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("unencrypted")
                         .action(SetTrue)
                         .help("generate an unencrypted secret key"),
+                )
+                .arg(
+                    Arg::new("namespaces")
+                        .long("namespaces")
+                        .num_args(1)
+                        .value_name("NAMESPACES")
+                        .help("comma-separated namespaces the key pair can be used for"),
                 ),
         )
         .subcommand(
@@ -59,8 +71,9 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("public-key-string")
                         .num_args(1)
                         .value_name("PUBLIC_KEY_STRING")
+                        .action(Append)
                         .conflicts_with("pk_path")
-                        .help("public key string"),
+                        .help("public key string (can be repeated)"),
                 )
                 .arg(
                     Arg::new("pk_path")
@@ -68,7 +81,9 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("public-key-path")
                         .num_args(1)
                         .value_name("PUBLIC_KEY_PATH")
-                        .help("path to public key file"),
+                        .value_parser(value_parser!(PathBuf))
+                        .action(Append)
+                        .help("path to public key file (can be repeated)"),
                 )
                 .arg(
                     Arg::new("sig_file")
@@ -76,8 +91,96 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("sig-file")
                         .num_args(1)
                         .value_name("SIG_FILE")
+                        .value_parser(value_parser!(PathBuf))
                         .help("signature file to be verified"),
                 )
+                .arg(
+                    Arg::new("threshold")
+                        .short('t')
+                        .long("threshold")
+                        .num_args(1)
+                        .value_name("COUNT")
+                        .value_parser(value_parser!(usize))
+                        .conflicts_with("format")
+                        .help("number of distinct public keys that must have signed (default: all)"),
+                )
+                .arg(
+                    Arg::new("certificate")
+                        .long("certificate")
+                        .num_args(1)
+                        .value_name("CERT_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .conflicts_with("format")
+                        .help("certificate of the signing key, if the signature doesn't embed one"),
+                )
+                .arg(
+                    Arg::new("purpose")
+                        .long("purpose")
+                        .num_args(1)
+                        .value_name("PURPOSE")
+                        .conflicts_with("format")
+                        .help("reject certified keys that aren't allowed this purpose"),
+                )
+                .arg(
+                    Arg::new("namespace")
+                        .long("namespace")
+                        .num_args(1)
+                        .value_name("NAMESPACE")
+                        .conflicts_with("format")
+                        .help("reject signatures made for other namespaces"),
+                )
+                .arg(
+                    Arg::new("at")
+                        .long("at")
+                        .num_args(1)
+                        .value_name("TIME")
+                        .conflicts_with("format")
+                        .help("check signature expiration at this time instead of now"),
+                )
+                .arg(
+                    Arg::new("state")
+                        .long("state")
+                        .num_args(1)
+                        .value_name("STATE_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .conflicts_with("format")
+                        .help("reject signatures older than the ones previously accepted, and remember this one"),
+                )
+                .arg(
+                    Arg::new("artifact")
+                        .long("artifact")
+                        .num_args(1)
+                        .value_name("NAME")
+                        .requires("state")
+                        .help("artifact name in the state file (default: the file name)"),
+                )
+                .arg(
+                    Arg::new("policy")
+                        .long("policy")
+                        .num_args(1)
+                        .value_name("POLICY_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .conflicts_with("format")
+                        .help("TOML file with rules that verified signatures must satisfy"),
+                )
+                .arg(
+                    Arg::new("require_countersign")
+                        .long("require-countersign")
+                        .num_args(1)
+                        .value_name("PUBLIC_KEY_PATH")
+                        .value_parser(value_parser!(PathBuf))
+                        .action(Append)
+                        .help("require a countersignature of the signature file by this key (can be repeated)"),
+                )
+                .arg(
+                    Arg::new("countersig_file")
+                        .long("countersig-file")
+                        .num_args(1)
+                        .value_name("COUNTERSIG_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .requires("require_countersign")
+                        .help("countersignature file (default: <SIG_FILE>.countersig)"),
+                )
                 .arg(
                     Arg::new("quiet")
                         .short('q')
@@ -97,15 +200,66 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .short('o')
                         .long("output")
                         .action(SetTrue)
+                        .conflicts_with("prehash")
                         .help("output the file content after verification"),
                 )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(SetTrue)
+                        .conflicts_with_all(["output", "format"])
+                        .help("print a JSON report of the verified signatures"),
+                )
+                .arg(
+                    Arg::new("comment-only")
+                        .long("comment-only")
+                        .action(SetTrue)
+                        .conflicts_with_all(["prehash", "sig_file", "output", "format"])
+                        .help("only verify the trusted comment of a signature file, without the data file"),
+                )
+                .arg(
+                    Arg::new("bind-filename")
+                        .long("bind-filename")
+                        .num_args(1)
+                        .value_name("MODE")
+                        .value_parser(["off", "basename", "path"])
+                        .conflicts_with("format")
+                        .help("check the file name recorded in the trusted comment (default: basename)"),
+                )
+                .arg(
+                    Arg::new("escape-comment")
+                        .long("escape-comment")
+                        .action(SetTrue)
+                        .conflicts_with("format")
+                        .help("display control characters in comments escaped instead of refusing them"),
+                )
+                .arg(
+                    Arg::new("prehash")
+                        .long("prehash")
+                        .num_args(1)
+                        .value_name("HEX")
+                        .conflicts_with("file")
+                        .requires("sig_file")
+                        .help("verify against a BLAKE2b-512 prehash instead of a file"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .num_args(1)
+                        .value_name("FORMAT")
+                        .value_parser(["minisign", "signify"])
+                        .default_value("minisign")
+                        .conflicts_with("prehash")
+                        .help("signature format (signify also covers OpenWrt usign)"),
+                )
                 .arg(
                     Arg::new("file")
                         .index(1)
                         .num_args(1)
-                        .required(true)
+                        .required_unless_present("prehash")
                         .value_name("FILE")
-                        .help("file to be verified"),
+                        .value_parser(value_parser!(PathBuf))
+                        .help("file to be verified, or the signature file with --comment-only"),
                 ),
         )
         .subcommand(
@@ -126,6 +280,7 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("public-key-file")
                         .num_args(1)
                         .value_name("PUBLIC_KEY_FILE")
+                        .value_parser(value_parser!(PathBuf))
                         .help("path to public key file"),
                 )
                 .arg(
@@ -134,6 +289,7 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("secret-key-file")
                         .num_args(1)
                         .value_name("SECRET_KEY_FILE")
+                        .value_parser(value_parser!(PathBuf))
                         .help("secret key to be used to sign"),
                 )
                 .arg(
@@ -142,6 +298,7 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("sig-file")
                         .num_args(1)
                         .value_name("SIG_FILE")
+                        .value_parser(value_parser!(PathBuf))
                         .help("signature file"),
                 )
                 .arg(
@@ -149,9 +306,19 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .index(1)
                         .num_args(1)
                         .value_name("FILE")
-                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .required_unless_present("prehash")
                         .help("file to sign"),
                 )
+                .arg(
+                    Arg::new("prehash")
+                        .long("prehash")
+                        .num_args(1)
+                        .value_name("HEX")
+                        .conflicts_with("data")
+                        .requires("sig_file")
+                        .help("sign a BLAKE2b-512 prehash instead of a file"),
+                )
                 .arg(
                     Arg::new("trusted-comment")
                         .short('t')
@@ -168,6 +335,15 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .value_name("UNTRUSTED_COMMENT")
                         .help("add a one-line untrusted comment"),
                 )
+                .arg(
+                    Arg::new("sanitize-comment")
+                        .long("sanitize-comment")
+                        .num_args(1)
+                        .value_name("MODE")
+                        .value_parser(["reject", "escape", "strip"])
+                        .default_value("reject")
+                        .help("reject, escape or strip control characters in the comments"),
+                )
                 .arg(
                     Arg::new("hash")
                         .short('H')
@@ -182,9 +358,789 @@ pub fn parse_args() -> (clap::ArgMatches, String) {
                         .long("passwordless")
                         .action(SetTrue)
                         .help("don't use a password for the secret key"),
+                )
+                .arg(
+                    Arg::new("append")
+                        .short('a')
+                        .long("append")
+                        .action(SetTrue)
+                        .help("add the signature to an existing signature file"),
+                )
+                .arg(
+                    Arg::new("certificate")
+                        .long("certificate")
+                        .num_args(1)
+                        .value_name("CERT_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("certificate of the secret key, to embed in the signature"),
+                )
+                .arg(
+                    Arg::new("namespace")
+                        .long("namespace")
+                        .num_args(1)
+                        .value_name("NAMESPACE")
+                        .help("bind the signature to a namespace, such as \"release\" or \"config\""),
+                )
+                .arg(
+                    Arg::new("expires")
+                        .long("expires")
+                        .num_args(1)
+                        .value_name("DURATION|DATE")
+                        .help("stop accepting the signature after a duration (such as 7d) or a date"),
+                ),
+        )
+        .subcommand(
+            Command::new("certify")
+                .about("Certify a subkey with a root (or already certified) key")
+                .arg(
+                    Arg::new("sk_path")
+                        .short('s')
+                        .long("secret-key-file")
+                        .num_args(1)
+                        .value_name("SECRET_KEY_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("secret key of the issuer"),
+                )
+                .arg(
+                    Arg::new("issuer_certificate")
+                        .long("certificate")
+                        .num_args(1)
+                        .value_name("CERT_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("certificate of the issuer, if the issuer isn't a root key"),
+                )
+                .arg(
+                    Arg::new("valid_from")
+                        .long("valid-from")
+                        .num_args(1)
+                        .value_name("YYYYMMDD[HHMM[SS]]")
+                        .help("reject signatures made before this time (UTC)"),
+                )
+                .arg(
+                    Arg::new("valid_until")
+                        .long("valid-until")
+                        .num_args(1)
+                        .value_name("YYYYMMDD[HHMM[SS]]")
+                        .help("reject signatures made after this time (UTC)"),
+                )
+                .arg(
+                    Arg::new("files")
+                        .long("files")
+                        .num_args(1)
+                        .value_name("GLOBS")
+                        .help("comma-separated patterns of the file names the subkey can sign"),
+                )
+                .arg(
+                    Arg::new("purpose")
+                        .long("purpose")
+                        .num_args(1)
+                        .value_name("PURPOSE")
+                        .help("comma-separated purposes the subkey can be used for"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .num_args(1)
+                        .value_name("CERT_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("certificate file (default: <SUBKEY>.cert)"),
+                )
+                .arg(
+                    Arg::new("passwordless")
+                        .short('W')
+                        .long("passwordless")
+                        .action(SetTrue)
+                        .help("don't use a password for the secret key"),
+                )
+                .arg(
+                    Arg::new("subkey")
+                        .index(1)
+                        .num_args(1)
+                        .required(true)
+                        .value_name("SUBKEY")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("public key to certify"),
+                ),
+        )
+        .subcommand(
+            Command::new("countersign")
+                .about("Countersign an existing signature file with another key")
+                .arg(
+                    Arg::new("sk_path")
+                        .short('s')
+                        .long("secret-key-file")
+                        .num_args(1)
+                        .value_name("SECRET_KEY_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("secret key to be used to countersign"),
+                )
+                .arg(
+                    Arg::new("countersig_file")
+                        .short('x')
+                        .long("countersig-file")
+                        .num_args(1)
+                        .value_name("COUNTERSIG_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("countersignature file (default: <SIG_FILE>.countersig)"),
+                )
+                .arg(
+                    Arg::new("trusted-comment")
+                        .short('t')
+                        .long("trusted-comment")
+                        .num_args(1)
+                        .value_name("TRUSTED_COMMENT")
+                        .help("add a one-line trusted comment"),
+                )
+                .arg(
+                    Arg::new("untrusted-comment")
+                        .short('c')
+                        .long("untrusted-comment")
+                        .num_args(1)
+                        .value_name("UNTRUSTED_COMMENT")
+                        .help("add a one-line untrusted comment"),
+                )
+                .arg(
+                    Arg::new("sanitize-comment")
+                        .long("sanitize-comment")
+                        .num_args(1)
+                        .value_name("MODE")
+                        .value_parser(["reject", "escape", "strip"])
+                        .default_value("reject")
+                        .help("reject, escape or strip control characters in the comments"),
+                )
+                .arg(
+                    Arg::new("passwordless")
+                        .short('W')
+                        .long("passwordless")
+                        .action(SetTrue)
+                        .help("don't use a password for the secret key"),
+                )
+                .arg(
+                    Arg::new("append")
+                        .short('a')
+                        .long("append")
+                        .action(SetTrue)
+                        .help("add the countersignature to an existing countersignature file"),
+                )
+                .arg(
+                    Arg::new("sig_file")
+                        .index(1)
+                        .num_args(1)
+                        .required(true)
+                        .value_name("SIG_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("signature file to countersign"),
+                ),
+        )
+        .subcommand(
+            Command::new("convert")
+                .about("Convert keys and signatures between minisign and signify/usign formats")
+                .arg(
+                    Arg::new("to")
+                        .short('t')
+                        .long("to")
+                        .num_args(1)
+                        .value_name("FORMAT")
+                        .value_parser(["minisign", "signify", "usign"])
+                        .required(true)
+                        .help("output format"),
+                )
+                .arg(
+                    Arg::new("sk_path")
+                        .short('s')
+                        .long("secret-key-file")
+                        .num_args(1)
+                        .value_name("SECRET_KEY_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help(
+                            "secret key used to sign the trusted comment of converted signatures",
+                        ),
+                )
+                .arg(
+                    Arg::new("comment")
+                        .short('c')
+                        .long("comment")
+                        .num_args(1)
+                        .value_name("COMMENT")
+                        .help("one-line untrusted comment for the converted key or signature"),
+                )
+                .arg(
+                    Arg::new("passwordless")
+                        .short('W')
+                        .long("passwordless")
+                        .action(SetTrue)
+                        .help("don't use a password for the secret keys"),
+                )
+                .arg(
+                    Arg::new("unencrypted")
+                        .long("unencrypted")
+                        .action(SetTrue)
+                        .help("write an unencrypted secret key"),
+                )
+                .arg(
+                    Arg::new("input")
+                        .index(1)
+                        .num_args(1)
+                        .required(true)
+                        .value_name("INPUT")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("public key, secret key or signature to convert"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .index(2)
+                        .num_args(1)
+                        .required(true)
+                        .value_name("OUTPUT")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("file to write the converted key or signature to"),
+                ),
+        )
+        .subcommand(
+            Command::new("ssh-keygen")
+                .about("Create and verify SSH signatures, with the ssh-keygen -Y interface used by git")
+                .arg(
+                    Arg::new("op")
+                        .short('Y')
+                        .num_args(1)
+                        .value_name("OPERATION")
+                        .value_parser(["sign", "verify", "find-principals", "check-novalidate"])
+                        .help("signature operation"),
+                )
+                .arg(
+                    Arg::new("print_public_key")
+                        .short('y')
+                        .action(SetTrue)
+                        .requires("key_file")
+                        .help("print the public key of a key file in OpenSSH format"),
+                )
+                .group(
+                    ArgGroup::new("operation")
+                        .args(["op", "print_public_key"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("key_file")
+                        .short('f')
+                        .num_args(1)
+                        .value_name("FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("secret key to sign with, or allowed signers file to verify with"),
+                )
+                .arg(
+                    Arg::new("namespace")
+                        .short('n')
+                        .num_args(1)
+                        .value_name("NAMESPACE")
+                        .help("signature namespace, such as git or file"),
+                )
+                .arg(
+                    Arg::new("principal")
+                        .short('I')
+                        .num_args(1)
+                        .value_name("PRINCIPAL")
+                        .help("identity of the expected signer"),
+                )
+                .arg(
+                    Arg::new("sig_file")
+                        .short('s')
+                        .num_args(1)
+                        .value_name("SIG_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("signature file to verify"),
+                )
+                .arg(
+                    Arg::new("revocation_file")
+                        .short('r')
+                        .num_args(1)
+                        .value_name("REVOCATION_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("file listing revoked public keys"),
+                )
+                .arg(
+                    Arg::new("option")
+                        .short('O')
+                        .num_args(1)
+                        .action(Append)
+                        .value_name("OPTION")
+                        .help("hashalg=sha256|sha512 or verify-time=YYYYMMDD[HHMM[SS]]"),
+                )
+                .arg(
+                    Arg::new("agent")
+                        .short('U')
+                        .action(SetTrue)
+                        .help("use a key from ssh-agent (unsupported)"),
+                )
+                .arg(
+                    Arg::new("files")
+                        .index(1)
+                        .num_args(0..)
+                        .value_name("FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("files to sign"),
+                ),
+        )
+        .subcommand(
+            Command::new("export-pk")
+                .about("Export a public key as JWK, PEM, DER, OpenSSH or raw hex")
+                .arg(
+                    Arg::new("public_key")
+                        .short('P')
+                        .long("public-key-string")
+                        .num_args(1)
+                        .value_name("PUBLIC_KEY_STRING")
+                        .conflicts_with("pk_path")
+                        .help("public key string"),
+                )
+                .arg(
+                    Arg::new("pk_path")
+                        .short('p')
+                        .long("public-key-path")
+                        .num_args(1)
+                        .value_name("PUBLIC_KEY_PATH")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("path to public key file"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .num_args(1)
+                        .value_name("FORMAT")
+                        .value_parser(["jwk", "pem", "der", "openssh", "raw-hex"])
+                        .required(true)
+                        .help("output format"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .num_args(1)
+                        .value_name("OUTPUT")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("file to write the exported key to, instead of the standard output"),
+                ),
+        )
+        .subcommand(
+            Command::new("import-pk")
+                .about("Import a public key from JWK, PEM, DER, OpenSSH or raw hex")
+                .arg(
+                    Arg::new("pk_path")
+                        .short('p')
+                        .long("public-key-path")
+                        .num_args(1)
+                        .value_name("PUBLIC_KEY_PATH")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("path to the new public key"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .num_args(1)
+                        .value_name("FORMAT")
+                        .value_parser(["jwk", "pem", "der", "openssh", "raw-hex"])
+                        .required(true)
+                        .help("input format"),
+                )
+                .arg(
+                    Arg::new("key_id")
+                        .long("key-id")
+                        .num_args(1)
+                        .value_name("KEY_ID")
+                        .help("key ID to assign (default: the JWK kid, or a random ID)"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .action(SetTrue)
+                        .help("overwrite an existing public key"),
+                )
+                .arg(
+                    Arg::new("input")
+                        .index(1)
+                        .num_args(1)
+                        .required(true)
+                        .value_name("INPUT")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("public key to import"),
+                ),
+        )
+        .subcommand(
+            Command::new("import-key")
+                .about("Import an existing Ed25519 secret key from OpenSSH, PKCS#8 or a raw seed")
+                .arg(
+                    Arg::new("pk_path")
+                        .short('p')
+                        .long("public-key-path")
+                        .num_args(1)
+                        .value_name("PUBLIC_KEY_PATH")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("path to the new public key"),
+                )
+                .arg(
+                    Arg::new("sk_path")
+                        .short('s')
+                        .long("secret-key-path")
+                        .num_args(1)
+                        .value_name("SECRET_KEY_PATH")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("path to the new secret key"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .num_args(1)
+                        .value_name("FORMAT")
+                        .value_parser(["openssh", "pkcs8", "raw-seed"])
+                        .required(true)
+                        .help("input format"),
+                )
+                .arg(
+                    Arg::new("key_id")
+                        .long("key-id")
+                        .num_args(1)
+                        .value_name("KEY_ID")
+                        .help("key ID to assign (default: a random ID)"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .action(SetTrue)
+                        .help("overwrite an existing key pair"),
+                )
+                .arg(
+                    Arg::new("comment")
+                        .short('c')
+                        .long("comment")
+                        .num_args(1)
+                        .value_name("COMMENT")
+                        .help("add a one-line untrusted comment"),
+                )
+                .arg(
+                    Arg::new("passwordless")
+                        .short('W')
+                        .long("passwordless")
+                        .action(SetTrue)
+                        .help("don't use a password for the secret key"),
+                )
+                .arg(
+                    Arg::new("unencrypted")
+                        .long("unencrypted")
+                        .action(SetTrue)
+                        .help("write an unencrypted secret key"),
+                )
+                .arg(
+                    Arg::new("input")
+                        .index(1)
+                        .num_args(1)
+                        .required(true)
+                        .value_name("INPUT")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("secret key to import"),
+                ),
+        )
+        .subcommand(
+            Command::new("state")
+                .about("Inspect or reset a verifier state file")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show")
+                        .about("List the newest signatures accepted for each key and artifact")
+                        .arg(
+                            Arg::new("state_file")
+                                .index(1)
+                                .num_args(1)
+                                .required(true)
+                                .value_name("STATE_FILE")
+                                .value_parser(value_parser!(PathBuf))
+                                .help("state file"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("reset")
+                        .about("Forget accepted signatures, so that older ones are accepted again")
+                        .arg(
+                            Arg::new("key_id")
+                                .long("key-id")
+                                .num_args(1)
+                                .value_name("KEY_ID")
+                                .help("only forget signatures by this key"),
+                        )
+                        .arg(
+                            Arg::new("artifact")
+                                .long("artifact")
+                                .num_args(1)
+                                .value_name("NAME")
+                                .help("only forget signatures of this artifact"),
+                        )
+                        .arg(
+                            Arg::new("state_file")
+                                .index(1)
+                                .num_args(1)
+                                .required(true)
+                                .value_name("STATE_FILE")
+                                .value_parser(value_parser!(PathBuf))
+                                .help("state file"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("sig")
+                .about("Check or fix signature files")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("check")
+                        .about("Report malformed lines, CRLF endings, byte order marks and stray whitespace")
+                        .arg(
+                            Arg::new("sig_files")
+                                .index(1)
+                                .num_args(1..)
+                                .required(true)
+                                .value_name("SIG_FILE")
+                                .value_parser(value_parser!(PathBuf))
+                                .help("signature files"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("normalize")
+                        .about("Rewrite signature files in canonical form")
+                        .arg(
+                            Arg::new("sig_files")
+                                .index(1)
+                                .num_args(1..)
+                                .required(true)
+                                .value_name("SIG_FILE")
+                                .value_parser(value_parser!(PathBuf))
+                                .help("signature files"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("set-untrusted-comment")
+                        .about("Change the untrusted comment, which doesn't invalidate the signature")
+                        .arg(
+                            Arg::new("key_id")
+                                .long("key-id")
+                                .num_args(1)
+                                .value_name("KEY_ID")
+                                .help("only change the signature by this key"),
+                        )
+                        .arg(
+                            Arg::new("sig_file")
+                                .index(1)
+                                .num_args(1)
+                                .required(true)
+                                .value_name("SIG_FILE")
+                                .value_parser(value_parser!(PathBuf))
+                                .help("signature file"),
+                        )
+                        .arg(
+                            Arg::new("untrusted_comment")
+                                .index(2)
+                                .num_args(1)
+                                .required(true)
+                                .value_name("UNTRUSTED_COMMENT")
+                                .help("new untrusted comment"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the configuration files")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show")
+                        .about("Print the resolved defaults and the file each one comes from"),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Move keys and configuration from ~/.rsign to the XDG configuration directory")
+                .arg(
+                    Arg::new("dry_run")
+                        .short('n')
+                        .long("dry-run")
+                        .action(SetTrue)
+                        .help("only print the files that would be moved"),
+                ),
+        )
+        .subcommand(
+            Command::new("compat")
+                .about("Run with the command-line interface of minisign, as when installed as minisign")
+                .disable_help_flag(true)
+                .arg(
+                    Arg::new("args")
+                        .num_args(0..)
+                        .allow_hyphen_values(true)
+                        .trailing_var_arg(true)
+                        .value_name("MINISIGN_ARGS")
+                        .value_parser(value_parser!(OsString))
+                        .help("minisign arguments"),
+                ),
+        )
+        .subcommand(
+            Command::new("hash")
+                .about("Compute the prehash of a file, as signed by the sign command")
+                .arg(
+                    Arg::new("base64")
+                        .short('b')
+                        .long("base64")
+                        .action(SetTrue)
+                        .help("print the hash encoded as base64 instead of hex"),
+                )
+                .arg(
+                    Arg::new("file")
+                        .index(1)
+                        .num_args(1)
+                        .required(true)
+                        .value_name("FILE")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("file to hash"),
                 ),
         );
     let help_usage = app.render_usage().to_string();
     let matches = app.get_matches();
     (matches, help_usage)
 }
+
+/// Whether the program was invoked as `minisign`, for example through a
+/// symbolic link.
+pub fn invoked_as_minisign() -> bool {
+    std::env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .and_then(|path| {
+            path.file_stem()
+                .map(|stem| stem.eq_ignore_ascii_case("minisign"))
+        })
+        .unwrap_or(false)
+}
+
+/// The command-line grammar of the C implementation of minisign.
+pub fn minisign_command() -> Command {
+    let flag = |id: &'static str, short: char, help: &'static str| {
+        Arg::new(id).short(short).action(SetTrue).help(help)
+    };
+    let value = |id: &'static str, short: char, value_name: &'static str, help: &'static str| {
+        Arg::new(id)
+            .short(short)
+            .num_args(1)
+            .value_name(value_name)
+            .help(help)
+    };
+    Command::new("minisign")
+        .about("minisign-compatible interface of rsign")
+        .disable_version_flag(true)
+        .arg(flag("generate", 'G', "generate a new key pair"))
+        .arg(flag(
+            "recreate",
+            'R',
+            "recreate a public key file from a secret key file",
+        ))
+        .arg(flag(
+            "change_password",
+            'C',
+            "change/remove the password of the secret key",
+        ))
+        .arg(flag("sign", 'S', "sign files"))
+        .arg(flag(
+            "verify",
+            'V',
+            "verify that a signature is valid for a given file",
+        ))
+        .arg(flag("version", 'v', "display version number"))
+        .group(
+            ArgGroup::new("action")
+                .args([
+                    "generate",
+                    "recreate",
+                    "change_password",
+                    "sign",
+                    "verify",
+                    "version",
+                ])
+                .required(true),
+        )
+        .arg(flag(
+            "prehashed",
+            'H',
+            "require the signature to be prehashed",
+        ))
+        .arg(flag("legacy", 'l', "sign using the legacy format"))
+        .arg(
+            Arg::new("message_files")
+                .short('m')
+                .num_args(1..)
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .required_if_eq_any([("sign", "true"), ("verify", "true")])
+                .help("file to sign/verify"),
+        )
+        .arg(flag(
+            "output",
+            'o',
+            "combined with -V, output the file content after verification",
+        ))
+        .arg(
+            value(
+                "pk_path",
+                'p',
+                "PUBKEY_FILE",
+                "public key file (default: ./minisign.pub)",
+            )
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(value(
+            "public_key",
+            'P',
+            "PUBKEY",
+            "public key, as a base64 string",
+        ))
+        .arg(
+            value(
+                "sk_path",
+                's',
+                "SECKEY_FILE",
+                "secret key file (default: ~/.minisign/minisign.key)",
+            )
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(flag(
+            "passwordless",
+            'W',
+            "do not encrypt/decrypt the secret key with a password",
+        ))
+        .arg(
+            value(
+                "sig_file",
+                'x',
+                "SIGFILE",
+                "signature file (default: <file>.minisig)",
+            )
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(value(
+            "untrusted_comment",
+            'c',
+            "COMMENT",
+            "add a one-line untrusted comment",
+        ))
+        .arg(value(
+            "trusted_comment",
+            't',
+            "COMMENT",
+            "add a one-line trusted comment",
+        ))
+        .arg(flag("quiet", 'q', "quiet mode, suppress output"))
+        .arg(flag(
+            "pretty_quiet",
+            'Q',
+            "pretty quiet mode, only print the trusted comment",
+        ))
+        .arg(flag(
+            "force",
+            'f',
+            "force. Combined with -G, overwrite a previous key pair",
+        ))
+}
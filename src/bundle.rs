@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use minisign::*;
+
+use crate::platform;
+use crate::raw::{key_id, parse_signature_box};
+
+const SIGNATURE_BOX_LINES: usize = 4;
+
+/// One or more minisign signatures of the same data, stored as consecutive
+/// signature boxes. A bundle with a single signature is a regular minisign
+/// signature file.
+#[derive(Default)]
+pub struct SignatureBundle {
+    boxes: Vec<SignatureBox>,
+}
+
+impl SignatureBundle {
+    pub fn from_string(s: &str) -> Result<SignatureBundle> {
+        let lines: Vec<&str> = s.lines().filter(|line| !line.trim().is_empty()).collect();
+        if lines.is_empty() {
+            return Err(PError::new(ErrorKind::Io, "Missing untrusted comment"));
+        }
+        let mut boxes = vec![];
+        for chunk in lines.chunks(SIGNATURE_BOX_LINES) {
+            if chunk.len() != SIGNATURE_BOX_LINES {
+                return Err(PError::new(
+                    ErrorKind::Encoding,
+                    "truncated signature in signature bundle",
+                ));
+            }
+            boxes.push(parse_signature_box(&chunk.join("\n"))?);
+        }
+        Ok(SignatureBundle { boxes })
+    }
+
+    pub fn from_file<P>(path: P) -> Result<SignatureBundle>
+    where
+        P: AsRef<Path>,
+    {
+        let s = platform::read_to_string(path)?;
+        SignatureBundle::from_string(&s)
+    }
+
+    pub fn boxes(&self) -> &[SignatureBox] {
+        &self.boxes
+    }
+
+    pub fn boxes_mut(&mut self) -> &mut [SignatureBox] {
+        &mut self.boxes
+    }
+
+    /// Add a signature to the bundle. A key can only sign once.
+    pub fn push(&mut self, signature_box: SignatureBox) -> Result<()> {
+        if self
+            .boxes
+            .iter()
+            .any(|b| b.keynum() == signature_box.keynum())
+        {
+            return Err(PError::new(
+                ErrorKind::Misc,
+                format!(
+                    "the signature file already contains a signature by key {}",
+                    key_id(signature_box.keynum())
+                ),
+            ));
+        }
+        self.boxes.push(signature_box);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.boxes
+            .iter()
+            .flat_map(|signature_box| signature_box.to_bytes())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let mut bundle = SignatureBundle::default();
+        for _ in 0..2 {
+            let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+            let signature_box = sign(Some(&pk), &sk, &b"data"[..], None, None).unwrap();
+            bundle.push(signature_box.clone()).unwrap();
+            assert!(bundle.push(signature_box).is_err());
+        }
+        let encoded = String::from_utf8(bundle.to_bytes()).unwrap();
+        assert_eq!(encoded.lines().count(), 8);
+        let bundle2 = SignatureBundle::from_string(&encoded).unwrap();
+        assert_eq!(bundle2.boxes().len(), 2);
+        assert_eq!(bundle2.boxes()[1].keynum(), bundle.boxes()[1].keynum());
+        let truncated: Vec<&str> = encoded.lines().take(6).collect();
+        assert!(SignatureBundle::from_string(&truncated.join("\n")).is_err());
+    }
+
+    #[test]
+    fn test_short_global_signature() {
+        let s = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ERs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==
+";
+        assert!(SignatureBundle::from_string(s).is_err());
+    }
+}
@@ -0,0 +1,404 @@
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+use ct_codecs::{Base64, Decoder, Encoder};
+use minisign::*;
+
+use crate::comment;
+use crate::helpers::{create_sig_file, load_secret_key, unix_timestamp, Passphrase};
+use crate::platform;
+use crate::raw::{key_id, parse_signature_box};
+use crate::sshsig::match_pattern_list;
+
+pub const CERT_SUFFIX: &str = ".cert";
+
+/// The trusted comment field holding an embedded certificate.
+pub const CERT_FIELD: &str = "cert";
+
+const MAX_CHAIN_LENGTH: usize = 8;
+
+/// Restrictions on what a certified subkey can sign.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Constraints {
+    pub not_before: Option<u64>,
+    pub not_after: Option<u64>,
+    pub files: Option<String>,
+    pub purpose: Option<String>,
+}
+
+impl Constraints {
+    fn to_fields(&self) -> Result<String> {
+        let mut fields = String::new();
+        for (name, value) in [("files", &self.files), ("purpose", &self.purpose)] {
+            if let Some(value) = value {
+                if value.contains(['\t', '\r', '\n']) {
+                    return Err(PError::new(
+                        ErrorKind::Misc,
+                        format!("the {name} constraint can't contain tabs or line breaks"),
+                    ));
+                }
+                fields.push_str(&format!("\t{name}:{value}"));
+            }
+        }
+        if let Some(not_before) = self.not_before {
+            fields.push_str(&format!("\tnot_before:{not_before}"));
+        }
+        if let Some(not_after) = self.not_after {
+            fields.push_str(&format!("\tnot_after:{not_after}"));
+        }
+        Ok(fields)
+    }
+
+    fn from_trusted_comment(trusted_comment: &str) -> Result<Constraints> {
+        let time = |name: &str| -> Result<Option<u64>> {
+            comment::field(trusted_comment, name)
+                .map(|ts| {
+                    ts.parse().map_err(|_| {
+                        PError::new(
+                            ErrorKind::Encoding,
+                            format!("invalid {name} in certificate"),
+                        )
+                    })
+                })
+                .transpose()
+        };
+        Ok(Constraints {
+            not_before: time("not_before")?,
+            not_after: time("not_after")?,
+            files: comment::field(trusted_comment, "files").map(str::to_string),
+            purpose: comment::field(trusted_comment, "purpose").map(str::to_string),
+        })
+    }
+
+    /// Check a signature made by a certified key, using the `timestamp:`
+    /// field of its trusted comment and the path of the verified file.
+    /// Certificates restricted to some files can't be checked without that
+    /// path, and certificates restricted to some purposes require one.
+    pub fn check(
+        &self,
+        trusted_comment: &str,
+        data_path: Option<&Path>,
+        purpose: Option<&str>,
+    ) -> Result<()> {
+        let violation = |reason: String| Err(PError::new(ErrorKind::Verify, reason));
+        if self.not_before.is_some() || self.not_after.is_some() {
+            let timestamp = match comment::timestamp(trusted_comment) {
+                Some(timestamp) => timestamp,
+                None => return violation("the signature has no timestamp".to_string()),
+            };
+            if self
+                .not_before
+                .is_some_and(|not_before| timestamp < not_before)
+                || self
+                    .not_after
+                    .is_some_and(|not_after| timestamp > not_after)
+            {
+                return violation(
+                    "the signature was made outside of the certificate's validity period"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(files) = &self.files {
+            let data_path = match data_path {
+                Some(data_path) => data_path,
+                None => {
+                    return violation(format!(
+                        "the certificate only allows signing some files ({files}), \
+                         which requires verifying the file itself"
+                    ))
+                }
+            };
+            let file = comment::encode_path(data_path);
+            let file_name = data_path
+                .file_name()
+                .map(|name| comment::encode_path(Path::new(name)));
+            if !match_pattern_list(files, &file)
+                && !file_name.is_some_and(|file_name| match_pattern_list(files, &file_name))
+            {
+                return violation(format!(
+                    "the certificate doesn't allow signing {file} (allowed: {files})"
+                ));
+            }
+        }
+        if let Some(allowed) = &self.purpose {
+            let purpose = match purpose {
+                Some(purpose) => purpose,
+                None => {
+                    return violation(format!(
+                        "the certificate only allows some purposes ({allowed}), \
+                         use --purpose to give the expected one"
+                    ))
+                }
+            };
+            if !match_pattern_list(allowed, purpose) {
+                return violation(format!(
+                    "the certificate doesn't allow the {purpose} purpose (allowed: {allowed})"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A subkey certified by another key. This is a regular minisign signature
+/// of the subkey's public key, whose trusted comment holds the subkey, its
+/// constraints, and the certificate of the issuer if the issuer isn't a root.
+#[derive(Clone)]
+pub struct Certificate {
+    signature_box: SignatureBox,
+    subkey: PublicKey,
+    constraints: Constraints,
+    issuer_certificate: Option<Box<Certificate>>,
+}
+
+impl Certificate {
+    pub fn issue(
+        issuer_pk: &PublicKey,
+        issuer_sk: &SecretKey,
+        issuer_certificate: Option<&Certificate>,
+        subkey: &PublicKey,
+        constraints: &Constraints,
+    ) -> Result<Certificate> {
+        if let Some(issuer_certificate) = issuer_certificate {
+            if issuer_certificate.subkey.to_bytes() != issuer_pk.to_bytes() {
+                return Err(PError::new(
+                    ErrorKind::Misc,
+                    "the issuer certificate wasn't issued for the signing key",
+                ));
+            }
+        }
+        let mut trusted_comment = format!(
+            "timestamp:{}\tsubkey:{}{}",
+            unix_timestamp(),
+            subkey.to_base64(),
+            constraints.to_fields()?
+        );
+        if let Some(issuer_certificate) = issuer_certificate {
+            trusted_comment.push_str(&format!("\tissuer_cert:{}", issuer_certificate.encode()?));
+        }
+        let untrusted_comment = format!("rsign certificate for key {}", key_id(subkey.keynum()));
+        let signature_box = sign(
+            Some(issuer_pk),
+            issuer_sk,
+            Cursor::new(subkey.to_bytes()),
+            Some(&trusted_comment),
+            Some(&untrusted_comment),
+        )?;
+        Certificate::from_signature_box(signature_box)
+    }
+
+    fn from_signature_box(signature_box: SignatureBox) -> Result<Certificate> {
+        Certificate::from_signature_box_at_depth(signature_box, 0)
+    }
+
+    fn from_signature_box_at_depth(
+        signature_box: SignatureBox,
+        depth: usize,
+    ) -> Result<Certificate> {
+        if depth >= MAX_CHAIN_LENGTH {
+            return Err(PError::new(
+                ErrorKind::Encoding,
+                "the certificate chain is too long",
+            ));
+        }
+        let trusted_comment = signature_box.trusted_comment()?;
+        let subkey = comment::field(&trusted_comment, "subkey")
+            .ok_or_else(|| PError::new(ErrorKind::Encoding, "not a certificate"))?;
+        let subkey = PublicKey::from_base64(subkey)?;
+        let constraints = Constraints::from_trusted_comment(&trusted_comment)?;
+        let issuer_certificate = match comment::field(&trusted_comment, "issuer_cert") {
+            Some(encoded) => Some(Box::new(Certificate::decode_at_depth(encoded, depth + 1)?)),
+            None => None,
+        };
+        Ok(Certificate {
+            signature_box,
+            subkey,
+            constraints,
+            issuer_certificate,
+        })
+    }
+
+    pub fn from_file<P>(path: P) -> Result<Certificate>
+    where
+        P: AsRef<Path>,
+    {
+        Certificate::from_signature_box(parse_signature_box(&platform::read_to_string(path)?)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.signature_box.to_bytes()
+    }
+
+    /// Encoding used to embed a certificate into a trusted comment.
+    pub fn encode(&self) -> Result<String> {
+        Ok(Base64::encode_to_string(self.to_bytes())?)
+    }
+
+    pub fn decode(encoded: &str) -> Result<Certificate> {
+        Certificate::decode_at_depth(encoded, 0)
+    }
+
+    fn decode_at_depth(encoded: &str, depth: usize) -> Result<Certificate> {
+        let bytes = Base64::decode_to_vec(encoded, None)?;
+        let s = String::from_utf8(bytes)
+            .map_err(|_| PError::new(ErrorKind::Encoding, "invalid embedded certificate"))?;
+        Certificate::from_signature_box_at_depth(parse_signature_box(&s)?, depth)
+    }
+
+    pub fn subkey(&self) -> &PublicKey {
+        &self.subkey
+    }
+
+    /// Verify the chain of certificates up to `root`, which doesn't have to
+    /// be the top of the chain. Returns the certified subkey and the
+    /// constraints of every certificate up to `root`.
+    pub fn verify_chain(&self, root: &PublicKey) -> Result<(PublicKey, Vec<Constraints>)> {
+        let (issuer, mut constraints) = match &self.issuer_certificate {
+            Some(issuer_certificate) if self.signature_box.keynum() != root.keynum() => {
+                issuer_certificate.verify_chain(root)?
+            }
+            _ => (root.clone(), vec![]),
+        };
+        if self.signature_box.keynum() != issuer.keynum() {
+            return Err(PError::new(
+                ErrorKind::Verify,
+                format!(
+                    "the certificate for key {} wasn't issued by key {}",
+                    key_id(self.subkey.keynum()),
+                    key_id(issuer.keynum())
+                ),
+            ));
+        }
+        verify(
+            &issuer,
+            &self.signature_box,
+            Cursor::new(self.subkey.to_bytes()),
+            true,
+            false,
+            false,
+        )?;
+        constraints.push(self.constraints.clone());
+        Ok((self.subkey.clone(), constraints))
+    }
+}
+
+/// Certify a public key with an issuer key, which is either a root key or a
+/// key certified by `issuer_certificate_path`.
+pub fn cmd_certify<P, Q, R>(
+    issuer_pk: Option<PublicKey>,
+    issuer_sk_path: P,
+    issuer_certificate_path: Option<Q>,
+    subkey: &PublicKey,
+    constraints: &Constraints,
+    certificate_path: R,
+    passphrase: &Passphrase,
+) -> Result<Certificate>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    let issuer_certificate = issuer_certificate_path
+        .map(Certificate::from_file)
+        .transpose()?;
+    let issuer_sk = load_secret_key(issuer_sk_path, passphrase)?;
+    let issuer_pk = match issuer_pk {
+        Some(issuer_pk) => issuer_pk,
+        None => PublicKey::from_secret_key(&issuer_sk)?,
+    };
+    let certificate = Certificate::issue(
+        &issuer_pk,
+        &issuer_sk,
+        issuer_certificate.as_ref(),
+        subkey,
+        constraints,
+    )?;
+    let mut certificate_writer = create_sig_file(certificate_path)?;
+    certificate_writer.write_all(&certificate.to_bytes())?;
+    certificate_writer.flush()?;
+    Ok(certificate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_chain() {
+        let root = KeyPair::generate_unencrypted_keypair().unwrap();
+        let intermediate = KeyPair::generate_unencrypted_keypair().unwrap();
+        let subkey = KeyPair::generate_unencrypted_keypair().unwrap();
+        let other = KeyPair::generate_unencrypted_keypair().unwrap();
+        let intermediate_constraints = Constraints {
+            purpose: Some("release,config".to_string()),
+            ..Default::default()
+        };
+        let intermediate_cert = Certificate::issue(
+            &root.pk,
+            &root.sk,
+            None,
+            &intermediate.pk,
+            &intermediate_constraints,
+        )
+        .unwrap();
+        let subkey_constraints = Constraints {
+            files: Some("*.tar.gz".to_string()),
+            ..Default::default()
+        };
+        let cert = Certificate::issue(
+            &intermediate.pk,
+            &intermediate.sk,
+            Some(&intermediate_cert),
+            &subkey.pk,
+            &subkey_constraints,
+        )
+        .unwrap();
+        let cert = Certificate::decode(&cert.encode().unwrap()).unwrap();
+        let (certified, constraints) = cert.verify_chain(&root.pk).unwrap();
+        assert_eq!(certified.to_bytes(), subkey.pk.to_bytes());
+        assert_eq!(
+            constraints,
+            vec![intermediate_constraints, subkey_constraints]
+        );
+        let (_, constraints) = cert.verify_chain(&intermediate.pk).unwrap();
+        assert_eq!(constraints.len(), 1);
+        assert!(cert.verify_chain(&other.pk).is_err());
+        assert!(Certificate::issue(
+            &other.pk,
+            &other.sk,
+            Some(&intermediate_cert),
+            &subkey.pk,
+            &Constraints::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_constraints() {
+        let constraints = Constraints {
+            not_before: Some(1000),
+            not_after: Some(2000),
+            files: Some("*.tar.gz,!*-rc*".to_string()),
+            purpose: Some("release".to_string()),
+        };
+        let tc = "timestamp:1500\tfile:app-1.0.tar.gz\tprehashed";
+        let file = Some(Path::new("dist/app-1.0.tar.gz"));
+        let release = Some("release");
+        assert!(constraints.check(tc, file, release).is_ok());
+        assert!(constraints.check(tc, file, Some("config")).is_err());
+        // Purpose-restricted certificates require a purpose
+        assert!(constraints.check(tc, file, None).is_err());
+        // Without the data, the files constraint can't be checked
+        assert!(constraints.check(tc, None, release).is_err());
+        let tc = "timestamp:2500\tfile:app-1.0.tar.gz\tprehashed";
+        assert!(constraints.check(tc, file, release).is_err());
+        // The verified file counts, not the name given by the signer
+        let tc = "timestamp:1500\tfile:app-1.0.tar.gz\tprehashed";
+        let rc = Some(Path::new("app-1.0-rc1.tar.gz"));
+        assert!(constraints.check(tc, rc, release).is_err());
+        assert!(constraints
+            .check("timestamp:1500\tprehashed", file, release)
+            .is_ok());
+    }
+}
@@ -0,0 +1,375 @@
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use minisign::{ErrorKind, PError, Result};
+
+use crate::helpers::is_printable_char;
+
+/// Longest trusted comment that minisign reads back: its line buffer is 8192
+/// bytes, including the `trusted comment: ` prefix, the newline and a NUL.
+pub const TRUSTED_COMMENT_MAX_BYTES: usize = 8192 - 17 - 2;
+
+/// Longest untrusted comment that minisign reads back, with a 1024 bytes
+/// line buffer and the `untrusted comment: ` prefix.
+pub const UNTRUSTED_COMMENT_MAX_BYTES: usize = 1024 - 19 - 2;
+
+/// What to do with control characters in comments given when signing,
+/// which would make the signature unverifiable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sanitize {
+    /// Refuse to sign.
+    #[default]
+    Reject,
+    /// Replace them with `\xNN` or `\u{NN}` escapes.
+    Escape,
+    /// Remove them.
+    Strip,
+}
+
+impl Sanitize {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "reject" => Ok(Sanitize::Reject),
+            "escape" => Ok(Sanitize::Escape),
+            "strip" => Ok(Sanitize::Strip),
+            _ => Err(PError::new(
+                ErrorKind::Misc,
+                format!("unknown comment sanitization mode [{}]", name),
+            )),
+        }
+    }
+}
+
+/// The comment with control characters escaped, safe to display.
+pub fn escape(comment: &str) -> String {
+    let mut escaped = String::with_capacity(comment.len());
+    for c in comment.chars() {
+        if is_printable_char(c) {
+            escaped.push(c);
+        } else if c.is_ascii() {
+            let _ = write!(escaped, "\\x{:02x}", c as u32);
+        } else {
+            let _ = write!(escaped, "\\u{{{:x}}}", c as u32);
+        }
+    }
+    escaped
+}
+
+/// Encode a path for the `file:` field of a trusted comment. Printable
+/// UTF-8 is kept as is, backslashes are doubled, ASCII control characters
+/// (including tabs) and bytes that aren't valid UTF-8 become `\xNN`, and
+/// other control characters or unpaired surrogates become `\u{NNNN}`.
+/// Different paths always have different encodings.
+pub fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    let push_char = |encoded: &mut String, c: char| match c {
+        '\\' => encoded.push_str("\\\\"),
+        c if c.is_ascii_control() => {
+            let _ = write!(encoded, "\\x{:02x}", c as u32);
+        }
+        c if !is_printable_char(c) => {
+            let _ = write!(encoded, "\\u{{{:x}}}", c as u32);
+        }
+        c => encoded.push(c),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+            for c in chunk.valid().chars() {
+                push_char(&mut encoded, c);
+            }
+            for byte in chunk.invalid() {
+                let _ = write!(encoded, "\\x{:02x}", byte);
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        for unit in char::decode_utf16(path.as_os_str().encode_wide()) {
+            match unit {
+                Ok(c) => push_char(&mut encoded, c),
+                Err(err) => {
+                    let _ = write!(encoded, "\\u{{{:x}}}", err.unpaired_surrogate());
+                }
+            }
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    for c in path.to_string_lossy().chars() {
+        push_char(&mut encoded, c);
+    }
+    // Whitespace at the ends of a trusted comment isn't kept
+    let start = encoded.len() - encoded.trim_start().len();
+    let end = start + encoded.trim().len();
+    if start == 0 && end == encoded.len() {
+        return encoded;
+    }
+    let escape_all = |s: &str| {
+        s.chars().fold(String::new(), |mut escaped, c| {
+            let _ = if c.is_ascii() {
+                write!(escaped, "\\x{:02x}", c as u32)
+            } else {
+                write!(escaped, "\\u{{{:x}}}", c as u32)
+            };
+            escaped
+        })
+    };
+    format!(
+        "{}{}{}",
+        escape_all(&encoded[..start]),
+        &encoded[start..end],
+        escape_all(&encoded[end..])
+    )
+}
+
+/// Decode a path encoded with `encode_path()`, or a `file:` field written
+/// by another implementation. Returns `None` for invalid escapes.
+pub fn decode_path(encoded: &str) -> Option<PathBuf> {
+    let mut bytes = vec![];
+    let mut surrogates = vec![];
+    let mut chars = encoded.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            '\\' => bytes.push(b'\\'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 {
+                    return None;
+                }
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+                let hex: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let code = u32::from_str_radix(&hex, 16).ok()?;
+                match char::from_u32(code) {
+                    Some(c) => {
+                        let mut buf = [0u8; 4];
+                        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    }
+                    None if (0xd800..0xe000).contains(&code) => {
+                        surrogates.push((bytes.len(), code as u16));
+                    }
+                    None => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+    os_string_from_parts(bytes, surrogates).map(PathBuf::from)
+}
+
+#[cfg(unix)]
+fn os_string_from_parts(bytes: Vec<u8>, surrogates: Vec<(usize, u16)>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+
+    if !surrogates.is_empty() {
+        return None;
+    }
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(windows)]
+fn os_string_from_parts(bytes: Vec<u8>, surrogates: Vec<(usize, u16)>) -> Option<OsString> {
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut wide = vec![];
+    let mut start = 0;
+    for (end, surrogate) in surrogates
+        .into_iter()
+        .chain(std::iter::once((bytes.len(), 0)))
+    {
+        wide.extend(std::str::from_utf8(&bytes[start..end]).ok()?.encode_utf16());
+        if surrogate != 0 {
+            wide.push(surrogate);
+        }
+        start = end;
+    }
+    Some(OsString::from_wide(&wide))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn os_string_from_parts(bytes: Vec<u8>, surrogates: Vec<(usize, u16)>) -> Option<OsString> {
+    if !surrogates.is_empty() {
+        return None;
+    }
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
+
+/// Check that a comment can be signed and read back, after escaping or
+/// stripping control characters according to `mode`. Whitespace at the ends
+/// is trimmed when reading a trusted comment, so it is refused or trimmed
+/// as well. `what` names the comment in errors.
+pub fn sanitize(comment: &str, what: &str, max_bytes: usize, mode: Sanitize) -> Result<String> {
+    let comment = match mode {
+        Sanitize::Reject => {
+            if let Some((position, c)) =
+                comment.char_indices().find(|(_, c)| !is_printable_char(*c))
+            {
+                return Err(PError::new(
+                    ErrorKind::Misc,
+                    format!(
+                        "the {} contains the control character {:?} at byte {}, which would make the signature unverifiable",
+                        what, c, position
+                    ),
+                ));
+            }
+            if comment.trim() != comment {
+                return Err(PError::new(
+                    ErrorKind::Misc,
+                    format!(
+                        "the {what} starts or ends with whitespace, which would make the signature unverifiable"
+                    ),
+                ));
+            }
+            comment.to_string()
+        }
+        Sanitize::Escape => escape(comment.trim()),
+        Sanitize::Strip => comment
+            .chars()
+            .filter(|c| is_printable_char(*c))
+            .collect::<String>()
+            .trim()
+            .to_string(),
+    };
+    check_length(&comment, what, max_bytes)?;
+    Ok(comment)
+}
+
+pub fn check_length(comment: &str, what: &str, max_bytes: usize) -> Result<()> {
+    if comment.len() > max_bytes {
+        return Err(PError::new(
+            ErrorKind::Misc,
+            format!(
+                "the {} is {} bytes long, the maximum is {}",
+                what,
+                comment.len(),
+                max_bytes
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Fields of a trusted comment, such as `timestamp:1556193335\tfile:foo`.
+/// Words without a colon, such as `prehashed`, have an empty value.
+pub fn fields(trusted_comment: &str) -> impl Iterator<Item = (&str, &str)> {
+    trusted_comment
+        .split('\t')
+        .filter(|field| !field.is_empty())
+        .map(|field| field.split_once(':').unwrap_or((field, "")))
+}
+
+pub fn field<'a>(trusted_comment: &'a str, name: &str) -> Option<&'a str> {
+    fields(trusted_comment).find_map(|(key, value)| (key == name).then_some(value))
+}
+
+pub fn timestamp(trusted_comment: &str) -> Option<u64> {
+    field(trusted_comment, "timestamp").and_then(|ts| ts.parse().ok())
+}
+
+/// The trusted comment without the given field, for display.
+pub fn without_field(trusted_comment: &str, name: &str) -> String {
+    trusted_comment
+        .split('\t')
+        .filter(|field| field.split_once(':').map_or(*field, |(key, _)| key) != name)
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::is_printable;
+
+    #[test]
+    fn test_fields() {
+        let tc = "timestamp:1556193335\tfile:a:b\tprehashed";
+        assert_eq!(timestamp(tc), Some(1556193335));
+        assert_eq!(field(tc, "file"), Some("a:b"));
+        assert_eq!(field(tc, "prehashed"), Some(""));
+        assert_eq!(field(tc, "cert"), None);
+        assert_eq!(without_field(tc, "file"), "timestamp:1556193335\tprehashed");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_encode_path() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let paths: [&[u8]; 6] = [
+            b"dir/caf\xc3\xa9.tar.gz",
+            b"caf\xe9",
+            b"a\tb\\x09\x1b",
+            b"\xc2\x85\xc2",
+            b"\\\\",
+            b" a b\xc2\xa0",
+        ];
+        let encoded: Vec<_> = paths
+            .iter()
+            .map(|path| encode_path(Path::new(std::ffi::OsStr::from_bytes(path))))
+            .collect();
+        assert_eq!(
+            encoded,
+            [
+                "dir/caf\u{e9}.tar.gz",
+                "caf\\xe9",
+                "a\\x09b\\\\x09\\x1b",
+                "\\u{85}\\xc2",
+                "\\\\\\\\",
+                "\\x20a b\\u{a0}",
+            ]
+        );
+        for (path, encoded) in paths.iter().zip(&encoded) {
+            assert!(is_printable(encoded) && !encoded.contains('\t'));
+            assert_eq!(decode_path(encoded).unwrap().as_os_str().as_bytes(), *path);
+        }
+        assert_eq!(decode_path("a\\x1"), None);
+        assert_eq!(decode_path("a\\q"), None);
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let tc = "a\tb\x1b[2Jc\rd\u{85}e";
+        let max = TRUSTED_COMMENT_MAX_BYTES;
+        let err = sanitize(tc, "trusted comment", max, Sanitize::Reject).unwrap_err();
+        assert!(err.to_string().contains("'\\u{1b}' at byte 3"), "{}", err);
+        assert_eq!(
+            sanitize(tc, "trusted comment", max, Sanitize::Escape).unwrap(),
+            "a\tb\\x1b[2Jc\\x0dd\\u{85}e"
+        );
+        assert_eq!(
+            sanitize(tc, "trusted comment", max, Sanitize::Strip).unwrap(),
+            "a\tb[2Jcde"
+        );
+        let long = "x".repeat(max + 1);
+        assert!(sanitize(&long[1..], "trusted comment", max, Sanitize::Reject).is_ok());
+        assert!(sanitize(&long, "trusted comment", max, Sanitize::Strip).is_err());
+
+        // Trusted comments are trimmed when read back
+        for tc in ["release 1 ", "\tlead"] {
+            let err = sanitize(tc, "trusted comment", max, Sanitize::Reject).unwrap_err();
+            assert!(err.to_string().contains("whitespace"), "{}", err);
+            assert_eq!(
+                sanitize(tc, "trusted comment", max, Sanitize::Escape).unwrap(),
+                tc.trim()
+            );
+        }
+        assert_eq!(
+            sanitize("a \x1b", "trusted comment", max, Sanitize::Strip).unwrap(),
+            "a"
+        );
+    }
+}
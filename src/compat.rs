@@ -0,0 +1,154 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use minisign::*;
+
+use crate::comment::{self, Sanitize, TRUSTED_COMMENT_MAX_BYTES, UNTRUSTED_COMMENT_MAX_BYTES};
+use crate::helpers::{
+    create_file, create_sig_file, load_secret_key, open_data_file, protect_secret_key,
+    unix_timestamp, with_suffix, Passphrase,
+};
+use crate::platform;
+use crate::prehash::sign_legacy;
+use crate::raw::{secret_key_bytes, secret_key_from_raw};
+
+/// Environment variable for the directory of the `minisign` secret key.
+pub const MINISIGN_CONFIG_DIR_ENV_VAR: &str = "MINISIGN_CONFIG_DIR";
+
+/// Default directory of the `minisign` secret key, in the home directory.
+pub const MINISIGN_CONFIG_DIR: &str = ".minisign";
+
+/// Default public key file of `minisign`.
+pub const MINISIGN_PKFILE: &str = "minisign.pub";
+
+/// Default secret key file of `minisign`.
+pub const MINISIGN_SKFILE: &str = "minisign.key";
+
+/// Default untrusted comment of `minisign` signatures.
+pub const MINISIGN_SIG_COMMENT: &str = "signature from minisign secret key";
+
+/// The default secret key path of `minisign`.
+pub fn minisign_sk_path() -> Result<PathBuf> {
+    let dir = match platform::env_var(MINISIGN_CONFIG_DIR_ENV_VAR) {
+        Some(env_path) => PathBuf::from(env_path),
+        None => platform::home_dir()
+            .ok_or_else(|| PError::new(ErrorKind::Io, "can't find home dir"))?
+            .join(MINISIGN_CONFIG_DIR),
+    };
+    Ok(dir.join(MINISIGN_SKFILE))
+}
+
+/// The trusted comment `minisign` adds by default, with the file name
+/// without its directory.
+pub fn minisign_trusted_comment(data_path: &Path, legacy: bool) -> String {
+    let file_name = comment::encode_path(data_path.file_name().map_or(data_path, Path::new));
+    if legacy {
+        format!("timestamp:{}\tfile:{}", unix_timestamp(), file_name)
+    } else {
+        format!("timestamp:{}\tfile:{}\thashed", unix_timestamp(), file_name)
+    }
+}
+
+/// Sign one or more files with the same key (`minisign -S`). The key is
+/// only decrypted once.
+pub fn cmd_sign_files(
+    sk_path: &Path,
+    data_paths: &[&Path],
+    signature_path: Option<&Path>,
+    trusted_comment: Option<&str>,
+    untrusted_comment: Option<&str>,
+    passphrase: &Passphrase,
+    legacy: bool,
+) -> Result<()> {
+    if !platform::exists(sk_path) {
+        return Err(PError::new(
+            ErrorKind::Io,
+            format!(
+                "can't find secret key file at {}, try using -s",
+                sk_path.display()
+            ),
+        ));
+    }
+    let sk = load_secret_key(sk_path, passphrase)?;
+    let untrusted_comment = comment::sanitize(
+        untrusted_comment.unwrap_or(MINISIGN_SIG_COMMENT),
+        "untrusted comment",
+        UNTRUSTED_COMMENT_MAX_BYTES,
+        Sanitize::Reject,
+    )?;
+    let untrusted_comment = untrusted_comment.as_str();
+    for data_path in data_paths {
+        let trusted_comment = match trusted_comment {
+            Some(trusted_comment) => trusted_comment.to_string(),
+            None => minisign_trusted_comment(data_path, legacy),
+        };
+        let trusted_comment = comment::sanitize(
+            &trusted_comment,
+            "trusted comment",
+            TRUSTED_COMMENT_MAX_BYTES,
+            Sanitize::Reject,
+        )?;
+        let signature_box = if legacy {
+            let data = platform::read(data_path)?;
+            sign_legacy(None, &sk, &data, &trusted_comment, Some(untrusted_comment))?
+        } else {
+            sign(
+                None,
+                &sk,
+                open_data_file(data_path)?,
+                Some(&trusted_comment),
+                Some(untrusted_comment),
+            )?
+        };
+        // Like minisign, `-x` is ignored when signing several files
+        let signature_path = match signature_path {
+            Some(signature_path) if data_paths.len() == 1 => signature_path.to_path_buf(),
+            _ => with_suffix(data_path, SIG_SUFFIX),
+        };
+        let mut signature_box_writer = create_sig_file(&signature_path)?;
+        signature_box_writer.write_all(&signature_box.to_bytes())?;
+        signature_box_writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Recreate the public key file of a secret key (`minisign -R`).
+pub fn cmd_recreate_pk(
+    sk_path: &Path,
+    pk_path: &Path,
+    passphrase: &Passphrase,
+) -> Result<PublicKey> {
+    let sk = load_secret_key(sk_path, passphrase)?;
+    let pk = PublicKey::from_secret_key(&sk)?;
+    if platform::exists(pk_path) {
+        platform::remove_file(pk_path)?;
+    }
+    let mut pk_writer = create_file(pk_path, 0o644)?;
+    pk_writer.write_all(pk.to_box()?.into_string().as_bytes())?;
+    pk_writer.flush()?;
+    Ok(pk)
+}
+
+/// Change the password of a secret key (`minisign -C`). With
+/// `passwordless`, the key is protected with an empty password.
+pub fn cmd_change_password(sk_path: &Path, passwordless: bool) -> Result<()> {
+    let sk = load_secret_key(sk_path, &Passphrase::Empty)
+        .or_else(|_| load_secret_key(sk_path, &Passphrase::Prompt))?;
+    // A decrypted key still records its KDF parameters, start from the raw key
+    let mut keynum = [0u8; 8];
+    keynum.copy_from_slice(sk.keynum());
+    let sk = secret_key_from_raw(&keynum, &secret_key_bytes(&sk))?;
+    let sk = protect_secret_key(&sk, passwordless, false)?;
+    let tmp_path = with_suffix(sk_path, format!(".tmp{}", std::process::id()));
+    let mut sk_writer = create_file(&tmp_path, 0o600)?;
+    sk_writer.write_all(sk.to_box(None)?.into_string().as_bytes())?;
+    sk_writer.flush()?;
+    drop(sk_writer);
+    platform::rename(&tmp_path, sk_path).map_err(|e| {
+        let _ = platform::remove_file(&tmp_path);
+        PError::new(
+            ErrorKind::Io,
+            format!("could not update {}: {}", sk_path.display(), e),
+        )
+    })
+}
@@ -0,0 +1,597 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use minisign::*;
+use serde::Deserialize;
+
+use crate::comment;
+use crate::filename::FileBinding;
+use crate::helpers::{create_dir, Passphrase};
+use crate::platform::{self, home_dir};
+
+/// Name of the user configuration file, in the configuration directory.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Name of the project configuration file, looked up from the current
+/// directory to the root.
+pub const PROJECT_CONFIG_FILE: &str = ".rsign.toml";
+
+/// Environment variable pointing to the XDG base configuration directory.
+pub const XDG_CONFIG_HOME_ENV_VAR: &str = "XDG_CONFIG_HOME";
+
+/// Name of the rsign directory in the XDG base configuration directory.
+pub const XDG_CONFIG_DIR: &str = "rsign";
+
+/// Template of the default trusted comment.
+pub const DEFAULT_TRUSTED_COMMENT: &str = "timestamp:{timestamp}\tfile:{file}\tprehashed";
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    secret_key: Option<String>,
+    public_key: Option<String>,
+    trusted_comment: Option<String>,
+    sig_suffix: Option<String>,
+    passphrase: Option<String>,
+    allow_legacy: Option<bool>,
+    policy: Option<String>,
+    bind_filename: Option<String>,
+}
+
+/// Keys that could weaken verification, and that only the user
+/// configuration file can set: a project file comes with the files it
+/// describes, and can't be trusted more than them.
+pub const USER_ONLY_KEYS: [&str; 5] = [
+    "public_key",
+    "passphrase",
+    "allow_legacy",
+    "policy",
+    "bind_filename",
+];
+
+/// Defaults read from the user and project configuration files. Values of
+/// the project file override those of the user file, except for
+/// `USER_ONLY_KEYS`, and command-line options override both.
+#[derive(Default)]
+pub struct Config {
+    pub secret_key: Option<PathBuf>,
+    pub public_key: Option<PathBuf>,
+    pub trusted_comment: Option<String>,
+    pub sig_suffix: Option<String>,
+    pub passphrase: Option<Passphrase>,
+    pub allow_legacy: Option<bool>,
+    pub policy: Option<PathBuf>,
+    pub bind_filename: Option<FileBinding>,
+    sources: BTreeMap<&'static str, PathBuf>,
+    /// User-only keys set by a project file, and that file.
+    ignored: BTreeMap<&'static str, PathBuf>,
+}
+
+/// The directory holding the default secret key and the user
+/// configuration file.
+pub enum ConfigDir {
+    /// Set by `SIG_DEFAULT_CONFIG_DIR_ENV_VAR`.
+    Env(PathBuf),
+    /// `~/.rsign`, used as long as it exists.
+    Legacy(PathBuf),
+    /// `$XDG_CONFIG_HOME/rsign`, or `~/.config/rsign`.
+    Xdg(PathBuf),
+}
+
+fn legacy_config_dir() -> Result<PathBuf> {
+    let home_path = home_dir().ok_or_else(|| PError::new(ErrorKind::Io, "can't find home dir"))?;
+    Ok(home_path.join(SIG_DEFAULT_CONFIG_DIR))
+}
+
+fn xdg_config_dir() -> Result<PathBuf> {
+    // Relative paths must be ignored, according to the XDG specification
+    let base = match platform::env_var(XDG_CONFIG_HOME_ENV_VAR).map(PathBuf::from) {
+        Some(base) if base.is_absolute() => base,
+        _ => home_dir()
+            .ok_or_else(|| PError::new(ErrorKind::Io, "can't find home dir"))?
+            .join(".config"),
+    };
+    Ok(base.join(XDG_CONFIG_DIR))
+}
+
+impl ConfigDir {
+    pub fn locate() -> Result<ConfigDir> {
+        if let Some(env_path) = platform::env_var(SIG_DEFAULT_CONFIG_DIR_ENV_VAR) {
+            return Ok(ConfigDir::Env(PathBuf::from(env_path)));
+        }
+        let legacy_dir = legacy_config_dir()?;
+        if platform::is_dir(&legacy_dir) {
+            return Ok(ConfigDir::Legacy(legacy_dir));
+        }
+        Ok(ConfigDir::Xdg(xdg_config_dir()?))
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            ConfigDir::Env(path) | ConfigDir::Legacy(path) | ConfigDir::Xdg(path) => path,
+        }
+    }
+}
+
+/// The user configuration file, in the configuration directory.
+pub fn user_config_path() -> Option<PathBuf> {
+    ConfigDir::locate()
+        .ok()
+        .map(|dir| dir.path().join(CONFIG_FILE))
+}
+
+/// The closest project configuration file in `dir` or its parents.
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| platform::is_file(path))
+}
+
+/// The directory of the closest project configuration file, or the current
+/// directory outside of projects.
+pub fn project_root() -> Option<PathBuf> {
+    let dir = platform::current_dir()?;
+    match find_project_config(&dir) {
+        Some(path) => path.parent().map(Path::to_path_buf),
+        None => Some(dir),
+    }
+}
+
+/// Paths are relative to the directory of the configuration file that
+/// sets them, and can start with `~/`.
+fn resolve_path(config_path: &Path, path: &str) -> PathBuf {
+    if let (Some(rest), Some(home)) = (path.strip_prefix("~/"), home_dir()) {
+        return home.join(rest);
+    }
+    config_path
+        .parent()
+        .map_or(PathBuf::from(path), |dir| dir.join(path))
+}
+
+impl Config {
+    /// Load the user configuration file and the project configuration file
+    /// of the current directory, if they exist.
+    pub fn load() -> Result<Config> {
+        let mut config = Config::default();
+        if let Some(path) = user_config_path().filter(|path| platform::is_file(path)) {
+            config.merge_file(&path)?;
+        }
+        let project_path = platform::current_dir().and_then(|dir| find_project_config(&dir));
+        if let Some(path) = project_path {
+            config.merge_project_file(&path)?;
+        }
+        Ok(config)
+    }
+
+    /// Override the current values with those of a user configuration file.
+    pub fn merge_file(&mut self, path: &Path) -> Result<()> {
+        self.merge(path, false)
+    }
+
+    /// Override the current values with those of a project configuration
+    /// file, ignoring `USER_ONLY_KEYS`.
+    pub fn merge_project_file(&mut self, path: &Path) -> Result<()> {
+        self.merge(path, true)
+    }
+
+    fn merge(&mut self, path: &Path, project: bool) -> Result<()> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            PError::new(
+                ErrorKind::Misc,
+                format!("invalid configuration file {}: {}", path.display(), e),
+            )
+        };
+        let s = platform::read_to_string(path).map_err(|e| {
+            PError::new(
+                ErrorKind::Io,
+                format!("could not read {}: {}", path.display(), e),
+            )
+        })?;
+        let mut file: ConfigFile = toml::from_str(&s).map_err(|e| invalid(&e))?;
+        if project {
+            let ignored = [
+                file.public_key.take().is_some(),
+                file.passphrase.take().is_some(),
+                file.allow_legacy.take().is_some(),
+                file.policy.take().is_some(),
+                file.bind_filename.take().is_some(),
+            ];
+            for (name, _) in USER_ONLY_KEYS.iter().zip(ignored).filter(|(_, set)| *set) {
+                self.ignored.insert(name, path.to_path_buf());
+            }
+        }
+        let passphrase = match file.passphrase.as_deref().map(Passphrase::from_name) {
+            Some(Ok(Passphrase::File(passphrase_path))) => Some(Passphrase::File(resolve_path(
+                path,
+                &passphrase_path.to_string_lossy(),
+            ))),
+            Some(passphrase) => Some(passphrase.map_err(|e| invalid(&e))?),
+            None => None,
+        };
+        let bind_filename = file
+            .bind_filename
+            .as_deref()
+            .map(FileBinding::from_name)
+            .transpose()
+            .map_err(|e| invalid(&e))?;
+        macro_rules! set {
+            ($field:ident, $value:expr) => {
+                if let Some(value) = $value {
+                    self.$field = Some(value);
+                    self.sources.insert(stringify!($field), path.to_path_buf());
+                }
+            };
+        }
+        let resolve = |s: Option<String>| s.map(|s| resolve_path(path, &s));
+        set!(secret_key, resolve(file.secret_key));
+        set!(public_key, resolve(file.public_key));
+        set!(trusted_comment, file.trusted_comment);
+        set!(sig_suffix, file.sig_suffix);
+        set!(passphrase, passphrase);
+        set!(allow_legacy, file.allow_legacy);
+        set!(policy, resolve(file.policy));
+        set!(bind_filename, bind_filename);
+        Ok(())
+    }
+
+    /// The configuration file that set a value, if any.
+    pub fn source(&self, name: &str) -> Option<&Path> {
+        self.sources.get(name).map(|path| path.as_path())
+    }
+
+    /// The user-only keys that a project file tried to set, and that file.
+    pub fn ignored(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.ignored
+            .iter()
+            .map(|(name, path)| (*name, path.as_path()))
+    }
+
+    pub fn sig_suffix(&self) -> &str {
+        self.sig_suffix.as_deref().unwrap_or(SIG_SUFFIX)
+    }
+
+    /// `-W` forces the empty password, the configured source is used
+    /// otherwise.
+    pub fn passphrase(&self, passwordless: bool) -> Passphrase {
+        match &self.passphrase {
+            Some(passphrase) if !passwordless => passphrase.clone(),
+            _ => Passphrase::from_flag(passwordless),
+        }
+    }
+
+    /// The trusted comment template, with `{timestamp}` and `{file}`
+    /// replaced, or `None` to use the built-in default.
+    pub fn trusted_comment(&self, timestamp: u64, file: Option<&Path>) -> Option<String> {
+        self.trusted_comment.as_ref().map(|template| {
+            template
+                .replace("{timestamp}", &timestamp.to_string())
+                .replace("{file}", &file.map_or(String::new(), comment::encode_path))
+        })
+    }
+}
+
+/// Return the secret key path to use for a new key, creating its directory,
+/// and remove an existing key if `force` is set.
+pub fn create_sk_path_or_default(sk_path: Option<PathBuf>, force: bool) -> Result<PathBuf> {
+    let sk_path = match sk_path {
+        Some(complete_path) => {
+            let mut dir = complete_path.clone();
+            dir.pop();
+            create_dir(&dir)?;
+            complete_path
+        }
+        None => {
+            let config_dir = ConfigDir::locate()?;
+            if !platform::exists(config_dir.path()) {
+                if let ConfigDir::Env(env_path) = &config_dir {
+                    return Err(PError::new(
+                        ErrorKind::Io,
+                        format!(
+                            "folder {} referenced by {} doesn't exist, you'll have to create \
+                             it yourself",
+                            env_path.display(),
+                            SIG_DEFAULT_CONFIG_DIR_ENV_VAR
+                        ),
+                    ));
+                }
+                create_dir(config_dir.path())?;
+            }
+            config_dir.path().join(SIG_DEFAULT_SKFILE)
+        }
+    };
+    if platform::exists(&sk_path) {
+        if !force {
+            return Err(PError::new(
+                ErrorKind::Io,
+                format!(
+                    "Key generation aborted:
+{} already exists
+
+If you really want to overwrite the existing key pair, add the -f switch to
+force this operation.",
+                    sk_path.display()
+                ),
+            ));
+        } else {
+            platform::remove_file(&sk_path)?;
+        }
+    }
+    Ok(sk_path)
+}
+
+/// Return the explicit public key path, the configured one, or the default.
+pub fn get_pk_path(explicit_path: Option<&Path>, config: &Config) -> Result<PathBuf> {
+    match (explicit_path, &config.public_key) {
+        (Some(explicit_path), _) => Ok(explicit_path.to_path_buf()),
+        (None, Some(config_path)) => Ok(config_path.clone()),
+        (None, None) => Ok(PathBuf::from(SIG_DEFAULT_PKFILE)),
+    }
+}
+
+/// Return the explicit secret key path, the configured one, or the default
+/// in the configuration directory.
+pub fn get_sk_path(explicit_path: Option<&Path>, config: &Config) -> Result<PathBuf> {
+    match (explicit_path, &config.secret_key) {
+        (Some(explicit_path), _) => Ok(explicit_path.to_path_buf()),
+        (None, Some(config_path)) => Ok(config_path.clone()),
+        (None, None) => Ok(ConfigDir::locate()?.path().join(SIG_DEFAULT_SKFILE)),
+    }
+}
+
+/// Move a file or a directory, copying it if it can't be renamed (for
+/// example across file systems). Permissions are preserved.
+fn move_entry(src: &Path, dst: &Path) -> Result<()> {
+    let platform = platform::current();
+    if platform.rename(src, dst).is_ok() {
+        return Ok(());
+    }
+    let mode = platform.mode(src)?;
+    if platform.is_dir(src) {
+        platform.create_dir_all(dst)?;
+        for entry in platform.read_dir(src)? {
+            if let Some(name) = entry.file_name() {
+                move_entry(&entry, &dst.join(name))?;
+            }
+        }
+        platform.set_mode(dst, mode)?;
+        platform.remove_dir(src)?;
+    } else {
+        let mut reader = platform.open(src)?;
+        let mut writer = platform.create_new(dst, mode)?;
+        std::io::copy(&mut reader, &mut writer)?;
+        writer.sync_all()?;
+        platform.set_mode(dst, mode)?;
+        platform.remove_file(src)?;
+    }
+    Ok(())
+}
+
+/// Move the keys and the configuration from `~/.rsign` to the XDG
+/// configuration directory. Returns the moved files and their new paths.
+pub fn cmd_migrate(dry_run: bool) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let legacy_dir = legacy_config_dir()?;
+    if !platform::is_dir(&legacy_dir) {
+        return Err(PError::new(
+            ErrorKind::Io,
+            format!("{} doesn't exist, nothing to migrate", legacy_dir.display()),
+        ));
+    }
+    let xdg_dir = xdg_config_dir()?;
+    let mut moves = vec![];
+    for src in platform::read_dir(&legacy_dir)? {
+        let dst = match src.file_name() {
+            Some(name) => xdg_dir.join(name),
+            None => continue,
+        };
+        if platform::exists(&dst) {
+            return Err(PError::new(
+                ErrorKind::Io,
+                format!(
+                    "{} already exists, move or remove it before migrating",
+                    dst.display()
+                ),
+            ));
+        }
+        moves.push((src, dst));
+    }
+    moves.sort();
+    if dry_run {
+        return Ok(moves);
+    }
+    if !platform::exists(&xdg_dir) {
+        platform::current().create_dir_all(&xdg_dir)?;
+        platform::set_mode(&xdg_dir, platform::mode(&legacy_dir)?)?;
+    }
+    for (src, dst) in &moves {
+        move_entry(src, dst).map_err(|e| {
+            PError::new(
+                ErrorKind::Io,
+                format!(
+                    "could not move {} to {}: {}",
+                    src.display(),
+                    dst.display(),
+                    e
+                ),
+            )
+        })?;
+    }
+    platform::remove_dir(&legacy_dir)?;
+    Ok(moves)
+}
+
+/// Print the resolved configuration, and where each value comes from.
+pub fn cmd_config_show(config: &Config, sk_path: &Path, pk_path: &Path) -> String {
+    let source = |name: &str| {
+        config
+            .source(name)
+            .map_or("default".to_string(), |path| path.display().to_string())
+    };
+    let values = [
+        ("secret_key", format!("{:?}", sk_path.display().to_string())),
+        ("public_key", format!("{:?}", pk_path.display().to_string())),
+        (
+            "trusted_comment",
+            format!(
+                "{:?}",
+                config
+                    .trusted_comment
+                    .as_deref()
+                    .unwrap_or(DEFAULT_TRUSTED_COMMENT)
+            ),
+        ),
+        ("sig_suffix", format!("{:?}", config.sig_suffix())),
+        (
+            "passphrase",
+            format!("{:?}", config.passphrase(false).to_string()),
+        ),
+        (
+            "allow_legacy",
+            config.allow_legacy.unwrap_or_default().to_string(),
+        ),
+        (
+            "bind_filename",
+            format!(
+                "{:?}",
+                match config.bind_filename.unwrap_or_default() {
+                    FileBinding::Off => "off",
+                    FileBinding::Basename => "basename",
+                    FileBinding::Path => "path",
+                }
+            ),
+        ),
+    ];
+    let mut out = String::new();
+    for (name, value) in values {
+        out.push_str(&format!("{name} = {value}\t# {}\n", source(name)));
+    }
+    match &config.policy {
+        Some(policy) => out.push_str(&format!(
+            "policy = {:?}\t# {}\n",
+            policy.display().to_string(),
+            source("policy")
+        )),
+        None => out.push_str("# policy is not set\n"),
+    }
+    for (name, path) in config.ignored() {
+        out.push_str(&format!(
+            "# {} is ignored in {}, only the user configuration file can set it\n",
+            name,
+            path.display()
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_layers() {
+        use crate::platform::{with_platform, MemoryPlatform};
+
+        let platform = MemoryPlatform::new(0);
+        let user_path = Path::new("/home/user/.config/rsign").join(CONFIG_FILE);
+        let project_dir = Path::new("/project");
+        let project_path = project_dir.join(PROJECT_CONFIG_FILE);
+        platform.write_file(
+            &user_path,
+            "secret_key = \"keys/me.key\"\nsig_suffix = \".sig\"\npassphrase = \"file:pw.txt\"\n",
+        );
+        platform.write_file(&project_path, "secret_key = \"release.key\"\n");
+        platform.write_file(project_dir.join("sub/file"), "data");
+        with_platform(platform.clone(), || {
+            assert_eq!(
+                find_project_config(&project_dir.join("sub")),
+                Some(project_path.clone())
+            );
+
+            let mut config = Config::default();
+            config.merge_file(&user_path).unwrap();
+            config.merge_project_file(&project_path).unwrap();
+            assert_eq!(config.secret_key, Some(project_dir.join("release.key")));
+            assert_eq!(config.source("secret_key"), Some(project_path.as_path()));
+            assert_eq!(config.sig_suffix(), ".sig");
+            assert_eq!(config.source("sig_suffix"), Some(user_path.as_path()));
+            assert_eq!(
+                config.passphrase(false),
+                Passphrase::File(PathBuf::from("/home/user/.config/rsign/pw.txt"))
+            );
+            assert_eq!(config.passphrase(true), Passphrase::Empty);
+
+            platform.write_file(&user_path, "secret_keys = \"x\"\n");
+            assert!(config.merge_file(&user_path).is_err());
+        });
+    }
+
+    #[test]
+    fn test_project_file_cannot_weaken_verification() {
+        use crate::platform::{with_platform, MemoryPlatform};
+
+        let platform = MemoryPlatform::new(0)
+            .with_home_dir("/home/user")
+            .with_current_dir("/project/sub");
+        platform.write_file(
+            "/home/user/.config/rsign/config.toml",
+            "bind_filename = \"path\"\n",
+        );
+        platform.write_file(
+            "/project/.rsign.toml",
+            "bind_filename = \"off\"\nallow_legacy = true\npublic_key = \"evil.pub\"\n\
+             sig_suffix = \".sig\"\n",
+        );
+        with_platform(platform, || {
+            let config = Config::load().unwrap();
+            assert_eq!(config.bind_filename, Some(FileBinding::Path));
+            assert_eq!(config.allow_legacy, None);
+            assert_eq!(config.public_key, None);
+            assert_eq!(config.sig_suffix(), ".sig");
+            let ignored: Vec<_> = config.ignored().map(|(name, _)| name).collect();
+            assert_eq!(ignored, ["allow_legacy", "bind_filename", "public_key"]);
+        });
+    }
+
+    #[test]
+    fn test_migrate_in_memory() {
+        use crate::platform::{with_platform, MemoryPlatform, Platform};
+
+        let platform = MemoryPlatform::new(0).with_home_dir("/home/user");
+        platform.write_file("/home/user/.rsign/rsign.key", "key");
+        platform.write_file("/home/user/.rsign/keys/other.key", "other");
+        platform
+            .set_mode(Path::new("/home/user/.rsign/rsign.key"), 0o600)
+            .unwrap();
+        let files = platform.clone();
+        with_platform(platform, || {
+            let moves = cmd_migrate(true).unwrap();
+            assert_eq!(
+                moves,
+                [
+                    (
+                        PathBuf::from("/home/user/.rsign/keys"),
+                        PathBuf::from("/home/user/.config/rsign/keys")
+                    ),
+                    (
+                        PathBuf::from("/home/user/.rsign/rsign.key"),
+                        PathBuf::from("/home/user/.config/rsign/rsign.key")
+                    ),
+                ]
+            );
+            assert!(platform::exists("/home/user/.rsign/rsign.key"));
+            cmd_migrate(false).unwrap();
+            assert!(!platform::exists("/home/user/.rsign"));
+            assert_eq!(
+                platform::mode("/home/user/.config/rsign/rsign.key").unwrap(),
+                0o600
+            );
+            assert!(cmd_migrate(false).is_err());
+        });
+        assert_eq!(
+            files
+                .read_file("/home/user/.config/rsign/keys/other.key")
+                .unwrap(),
+            b"other"
+        );
+        assert!(!Path::new("/home/user/.config/rsign").exists());
+    }
+}
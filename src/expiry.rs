@@ -0,0 +1,99 @@
+use minisign::*;
+
+use crate::comment;
+use crate::helpers::format_utc;
+use crate::sshsig::parse_ssh_time;
+
+/// The trusted comment field holding the expiration time of a signature.
+pub const EXPIRES_FIELD: &str = "expires";
+
+/// Parse a duration such as `45s`, `30m`, `12h`, `7d` or `2w`.
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let unit = match s.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 7 * 86400,
+        _ => return None,
+    };
+    let count: u64 = s[..s.len() - 1].parse().ok()?;
+    count.checked_mul(unit)
+}
+
+/// Parse a point in time: `YYYY-MM-DD[THH:MM[:SS]][Z]`, the OpenSSH
+/// `YYYYMMDD[HHMM[SS]][Z]` format, or `@` followed by a UNIX timestamp.
+/// Times are always interpreted as UTC.
+pub fn parse_time(s: &str) -> Result<u64> {
+    if let Some(timestamp) = s.strip_prefix('@') {
+        return timestamp
+            .parse()
+            .map_err(|_| PError::new(ErrorKind::Misc, format!("invalid time: {s}")));
+    }
+    let compact: String = s
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':' | 'T' | ' '))
+        .collect();
+    parse_ssh_time(&compact).map_err(|_| PError::new(ErrorKind::Misc, format!("invalid time: {s}")))
+}
+
+/// Parse an expiration given either as a duration from `now` or as a
+/// point in time.
+pub fn parse_expiry(s: &str, now: u64) -> Result<u64> {
+    match parse_duration(s) {
+        Some(duration) => now
+            .checked_add(duration)
+            .ok_or_else(|| PError::new(ErrorKind::Misc, format!("invalid expiration: {s}"))),
+        None => parse_time(s),
+    }
+}
+
+/// Reject a signature whose `expires:` time is before `at`.
+pub fn check_expiry(trusted_comment: &str, at: u64) -> Result<()> {
+    let expires = match comment::field(trusted_comment, EXPIRES_FIELD) {
+        Some(expires) => expires.parse::<u64>().map_err(|_| {
+            PError::new(
+                ErrorKind::Verify,
+                format!("invalid {EXPIRES_FIELD} field in the trusted comment"),
+            )
+        })?,
+        None => return Ok(()),
+    };
+    if at > expires {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            format!("Signature expired on {}", format_utc(expires)),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expiry() {
+        assert_eq!(parse_expiry("7d", 1000).unwrap(), 1000 + 7 * 86400);
+        assert_eq!(parse_expiry("2w", 0).unwrap(), 14 * 86400);
+        assert_eq!(parse_expiry("2024-02-29", 0).unwrap(), 1_709_164_800);
+        assert_eq!(
+            parse_expiry("2024-02-29T12:30:15Z", 0).unwrap(),
+            1_709_209_815
+        );
+        assert_eq!(parse_expiry("20240229123015", 0).unwrap(), 1_709_209_815);
+        assert_eq!(parse_time("@1709209815").unwrap(), 1_709_209_815);
+        assert!(parse_expiry("7y", 0).is_err());
+        assert!(parse_expiry("d", 0).is_err());
+    }
+
+    #[test]
+    fn test_check_expiry() {
+        let tc = "timestamp:1000\tfile:f\tprehashed\texpires:2000";
+        assert!(check_expiry(tc, 1500).is_ok());
+        assert!(check_expiry(tc, 2000).is_ok());
+        assert!(check_expiry(tc, 2001).is_err());
+        assert!(check_expiry("timestamp:1000", u64::MAX).is_ok());
+        assert!(check_expiry("timestamp:1000\texpires:soon", 0).is_err());
+    }
+}
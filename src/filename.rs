@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+use minisign::*;
+
+use crate::comment;
+
+/// The trusted comment field holding the path of the signed file.
+pub const FILE_FIELD: &str = "file";
+
+/// How the `file:` field of a trusted comment is compared with the path of
+/// the verified file. Trusted comments without this field are not checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileBinding {
+    Off,
+    /// The file names must match, regardless of their directories.
+    #[default]
+    Basename,
+    /// The paths must match exactly, as given when signing and verifying.
+    Path,
+}
+
+impl FileBinding {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "off" => Ok(FileBinding::Off),
+            "basename" => Ok(FileBinding::Basename),
+            "path" => Ok(FileBinding::Path),
+            _ => Err(PError::new(
+                ErrorKind::Misc,
+                format!("unknown file name binding [{name}] (expected off, basename or path)"),
+            )),
+        }
+    }
+}
+
+/// Reject a signature whose `file:` field names another file than
+/// `data_path`, such as a signature file renamed after another signed file.
+pub fn check_file_name(
+    trusted_comment: &str,
+    data_path: &Path,
+    binding: FileBinding,
+) -> Result<()> {
+    let field = match comment::field(trusted_comment, FILE_FIELD) {
+        Some(field) if binding != FileBinding::Off => field,
+        _ => return Ok(()),
+    };
+    // Fields written by other implementations may hold unescaped backslashes
+    let signed_path = comment::decode_path(field).unwrap_or_else(|| PathBuf::from(field));
+    let matches = match binding {
+        FileBinding::Off => true,
+        FileBinding::Basename => signed_path.file_name() == data_path.file_name(),
+        FileBinding::Path => signed_path == data_path,
+    };
+    if matches {
+        return Ok(());
+    }
+    Err(PError::new(
+        ErrorKind::Verify,
+        format!(
+            "the signature is for {FILE_FIELD}:{field}, not {} (use --bind-filename off to skip this check)",
+            comment::encode_path(data_path)
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_file_name() {
+        let tc = "timestamp:1\tfile:dist/good.tar.gz\tprehashed";
+        let check = |path: &str, binding| check_file_name(tc, Path::new(path), binding).is_ok();
+        assert!(check("good.tar.gz", FileBinding::Basename));
+        assert!(check("/tmp/good.tar.gz", FileBinding::Basename));
+        assert!(!check("evil.tar.gz", FileBinding::Basename));
+        assert!(check("evil.tar.gz", FileBinding::Off));
+        assert!(check("dist/good.tar.gz", FileBinding::Path));
+        assert!(!check("good.tar.gz", FileBinding::Path));
+        assert!(check_file_name("my comment", Path::new("evil"), FileBinding::Path).is_ok());
+        assert!(check_file_name("file:a\\b", Path::new("a\\b"), FileBinding::Path).is_ok());
+    }
+}
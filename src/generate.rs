@@ -0,0 +1,101 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use minisign::*;
+
+use crate::helpers::{create_file, get_new_password};
+use crate::namespace::record_namespaces;
+use crate::platform;
+use crate::raw::key_id;
+
+/// Options of `cmd_generate()`.
+#[derive(Clone, Debug, Default)]
+pub struct GenerateOptions<'a> {
+    /// Overwrite an existing public key.
+    pub force: bool,
+    /// Untrusted comment of the secret key.
+    pub comment: Option<&'a str>,
+    /// Encrypt the secret key with an empty password instead of asking for one.
+    pub passwordless: bool,
+    /// Don't encrypt the secret key at all.
+    pub unencrypted: bool,
+    /// Namespaces the key is allowed to sign for.
+    pub namespaces: Option<&'a str>,
+}
+
+/// A newly generated key pair, and where it was saved.
+pub struct GenerateOutcome {
+    pub key_pair: KeyPair,
+    pub key_id: String,
+    pub pk_path: PathBuf,
+    pub sk_path: PathBuf,
+}
+
+pub fn cmd_generate<P, Q>(
+    pk_path: P,
+    sk_path: Q,
+    options: &GenerateOptions<'_>,
+) -> Result<GenerateOutcome>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let pk_path = pk_path.as_ref();
+    let sk_path = sk_path.as_ref();
+    if platform::exists(pk_path) {
+        if !options.force {
+            return Err(PError::new(
+                ErrorKind::Io,
+                format!(
+                    "Key generation aborted:\n
+{} already exists\n
+If you really want to overwrite the existing key pair, add the -f switch to\n
+force this operation.",
+                    pk_path.display()
+                ),
+            ));
+        } else {
+            platform::remove_file(pk_path)?;
+        }
+    }
+    let mut pk_writer = create_file(pk_path, 0o644)?;
+    let mut sk_writer = create_file(sk_path, 0o600)?;
+    let (kp, mut pk_box, mut sk_box) = if options.unencrypted {
+        let kp = KeyPair::generate_unencrypted_keypair()?;
+        let pk_box = kp.pk.to_box()?.into_string();
+        let sk_box = kp.sk.to_box(options.comment)?.into_string();
+        (kp, pk_box, sk_box)
+    } else {
+        let mut pk_box = vec![];
+        let mut sk_box = vec![];
+        let kp = KeyPair::generate_and_write_encrypted_keypair(
+            &mut pk_box,
+            &mut sk_box,
+            options.comment,
+            Some(if options.passwordless {
+                Default::default()
+            } else {
+                get_new_password()?
+            }),
+        )?;
+        (
+            kp,
+            String::from_utf8_lossy(&pk_box).into_owned(),
+            String::from_utf8_lossy(&sk_box).into_owned(),
+        )
+    };
+    if let Some(namespaces) = options.namespaces {
+        pk_box = record_namespaces(&pk_box, namespaces)?;
+        sk_box = record_namespaces(&sk_box, namespaces)?;
+    }
+    pk_writer.write_all(pk_box.as_bytes())?;
+    sk_writer.write_all(sk_box.as_bytes())?;
+    pk_writer.flush()?;
+    sk_writer.flush()?;
+    Ok(GenerateOutcome {
+        key_id: key_id(kp.pk.keynum()),
+        key_pair: kp,
+        pk_path: pk_path.to_path_buf(),
+        sk_path: sk_path.to_path_buf(),
+    })
+}
@@ -0,0 +1,358 @@
+use std::ffi::OsStr;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use minisign::*;
+
+use crate::platform::{self, FileReader, FileWriter};
+use crate::raw::encrypt_secret_key;
+
+const PASSWORD_MAXBYTES: usize = 1024;
+
+pub fn open_data_file<P>(data_path: P) -> Result<BufReader<Box<dyn FileReader>>>
+where
+    P: AsRef<Path>,
+{
+    let data_path = data_path.as_ref();
+    let file = platform::current()
+        .open(data_path)
+        .map_err(|e| PError::new(ErrorKind::Io, e))?;
+
+    Ok(BufReader::new(file))
+}
+
+/// The path followed by a suffix, such as `file.minisig` for `file`. The
+/// suffix is appended to the raw OS string, so that non-UTF-8 names are
+/// preserved.
+pub fn with_suffix<S>(path: &Path, suffix: S) -> PathBuf
+where
+    S: AsRef<OsStr>,
+{
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+pub fn create_dir<P>(path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    platform::current()
+        .create_dir_all(path.as_ref())
+        .map_err(|e| {
+            PError::new(
+                ErrorKind::Io,
+                format!("while creating: {} - {}", path.as_ref().display(), e),
+            )
+        })?;
+    Ok(())
+}
+
+pub fn create_file<P>(path: P, mode: u32) -> Result<BufWriter<Box<dyn FileWriter>>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file = platform::current().create_new(path, mode).map_err(|e| {
+        PError::new(
+            ErrorKind::Io,
+            format!("while creating: {} - {}", path.display(), e),
+        )
+    })?;
+    Ok(BufWriter::new(file))
+}
+
+pub fn create_sig_file<P>(path: P) -> Result<BufWriter<Box<dyn FileWriter>>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file = platform::current().create_truncate(path).map_err(|e| {
+        PError::new(
+            ErrorKind::Io,
+            format!("while creating: {} - {}", path.display(), e),
+        )
+    })?;
+    Ok(BufWriter::new(file))
+}
+
+/// Ask for a password, through the current platform.
+pub fn get_password(prompt: &str) -> Result<String> {
+    let password = platform::prompt_password(prompt)?;
+    if password.len() > PASSWORD_MAXBYTES {
+        return Err(PError::new(
+            ErrorKind::Misc,
+            "passphrase can't exceed 1024 bytes length",
+        ));
+    }
+    Ok(password)
+}
+
+pub fn get_new_password() -> Result<String> {
+    let password = get_password("Please enter a password to protect the secret key.\nPassword: ")?;
+    let password2 = get_password("Password (one more time): ")?;
+    if password != password2 {
+        return Err(PError::new(ErrorKind::Generate, "passwords don't match!"));
+    }
+    Ok(password)
+}
+
+/// Where the password of an encrypted secret key comes from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Passphrase {
+    /// Ask interactively.
+    #[default]
+    Prompt,
+    /// The empty password (`-W`).
+    Empty,
+    /// The value of an environment variable.
+    Env(String),
+    /// The first line of a file.
+    File(PathBuf),
+}
+
+impl Passphrase {
+    /// The empty password with `-W`, an interactive prompt otherwise.
+    pub fn from_flag(passwordless: bool) -> Passphrase {
+        if passwordless {
+            Passphrase::Empty
+        } else {
+            Passphrase::Prompt
+        }
+    }
+
+    /// Parse `prompt`, `empty`, `env:NAME` or `file:PATH`.
+    pub fn from_name(s: &str) -> Result<Passphrase> {
+        match s.split_once(':') {
+            None if s == "prompt" => Ok(Passphrase::Prompt),
+            None if s == "empty" => Ok(Passphrase::Empty),
+            Some(("env", name)) if !name.is_empty() => Ok(Passphrase::Env(name.to_string())),
+            Some(("file", path)) if !path.is_empty() => Ok(Passphrase::File(PathBuf::from(path))),
+            _ => Err(PError::new(
+                ErrorKind::Misc,
+                format!(
+                    "invalid passphrase source: {s} (expected prompt, empty, env:NAME or file:PATH)"
+                ),
+            )),
+        }
+    }
+
+    /// The password, or `None` if it has to be asked for.
+    pub fn password(&self) -> Result<Option<String>> {
+        let password = match self {
+            Passphrase::Prompt => return Ok(None),
+            Passphrase::Empty => String::new(),
+            Passphrase::Env(name) => platform::env_var(name).ok_or_else(|| {
+                PError::new(
+                    ErrorKind::Misc,
+                    format!("environment variable {name} is not set"),
+                )
+            })?,
+            Passphrase::File(path) => {
+                let content = platform::read_to_string(path).map_err(|e| {
+                    PError::new(
+                        ErrorKind::Io,
+                        format!("could not read {}: {}", path.display(), e),
+                    )
+                })?;
+                content.lines().next().unwrap_or_default().to_string()
+            }
+        };
+        if password.len() > PASSWORD_MAXBYTES {
+            return Err(PError::new(
+                ErrorKind::Misc,
+                "passphrase can't exceed 1024 bytes length",
+            ));
+        }
+        Ok(Some(password))
+    }
+}
+
+impl std::fmt::Display for Passphrase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Passphrase::Prompt => write!(f, "prompt"),
+            Passphrase::Empty => write!(f, "empty"),
+            Passphrase::Env(name) => write!(f, "env:{name}"),
+            Passphrase::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+pub fn load_secret_key<P>(sk_path: P, passphrase: &Passphrase) -> Result<SecretKey>
+where
+    P: AsRef<Path>,
+{
+    let sk_str = platform::read_to_string(sk_path)?;
+    let sk_box: SecretKeyBox = sk_str.clone().into();
+    match sk_box.into_unencrypted_secret_key() {
+        Ok(sk) => Ok(sk),
+        Err(_) => {
+            let sk_box: SecretKeyBox = sk_str.into();
+            let password = match passphrase.password()? {
+                Some(password) => password,
+                None => get_password("Password: ")?,
+            };
+            sk_box.into_secret_key(Some(password))
+        }
+    }
+}
+
+/// Apply the usual protection choices to an unencrypted secret key: no
+/// encryption at all, encryption with an empty password, or a password
+/// read interactively.
+pub fn protect_secret_key(
+    sk: &SecretKey,
+    passwordless: bool,
+    unencrypted: bool,
+) -> Result<SecretKey> {
+    if unencrypted {
+        return Ok(sk.clone());
+    }
+    if passwordless {
+        return encrypt_secret_key(sk, "");
+    }
+    encrypt_secret_key(sk, &get_new_password()?)
+}
+
+/// The current time, from the clock of the current platform.
+pub fn unix_timestamp() -> u64 {
+    platform::current().now()
+}
+
+/// Convert a UTC calendar date and time to a UNIX timestamp.
+pub fn utc_timestamp(
+    year: u64,
+    month: u64,
+    day: u64,
+    hour: u64,
+    min: u64,
+    sec: u64,
+) -> Option<u64> {
+    const DAYS_IN_MONTH: [u64; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if !(1970..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || hour > 23
+        || min > 59
+        || sec > 60
+    {
+        return None;
+    }
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    if day < 1 || day > DAYS_IN_MONTH[month as usize - 1] || (month == 2 && day == 29 && !leap) {
+        return None;
+    }
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let days = y * 365 + y / 4 - y / 100 + y / 400 + (153 * m + 2) / 5 + day - 1 - 719_468;
+    Some(days * 86400 + hour * 3600 + min * 60 + sec)
+}
+
+/// Format a UNIX timestamp as a UTC date and time.
+pub fn format_utc(timestamp: u64) -> String {
+    let days = timestamp / 86400 + 719_468;
+    let secs = timestamp % 86400;
+    let era = days / 146_097;
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Control characters, other than tabs, are not allowed in comments.
+pub fn is_printable_char(c: char) -> bool {
+    !matches!(c, '\x00'..='\x08' | '\x0a'..='\x1f' | '\x7f'..='\u{9f}')
+}
+
+pub fn is_printable(s: &str) -> bool {
+    s.chars().all(is_printable_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_printable_normal_text() {
+        assert!(is_printable("hello world"));
+        assert!(is_printable("timestamp:1234567890"));
+    }
+
+    #[test]
+    fn test_printable_rejects_escape_sequence() {
+        assert!(!is_printable("\x1b[31mred\x1b[0m"));
+    }
+
+    #[test]
+    fn test_printable_rejects_control_characters() {
+        assert!(!is_printable("\x00"));
+        assert!(!is_printable("a\x07b"));
+        assert!(!is_printable("\x7f"));
+    }
+
+    #[test]
+    fn test_printable_rejects_newlines() {
+        assert!(!is_printable("line1\nline2"));
+        assert!(!is_printable("line1\r\nline2"));
+    }
+
+    #[test]
+    fn test_printable_allows_tab() {
+        assert!(is_printable("a\tb"));
+    }
+
+    #[test]
+    fn test_printable_allows_unicode() {
+        assert!(is_printable("héllo 世界"));
+    }
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(1970, 1, 1, 0, 0, 0), Some(0));
+        assert_eq!(utc_timestamp(2000, 3, 1, 0, 0, 0), Some(951_868_800));
+        assert_eq!(utc_timestamp(2024, 2, 29, 12, 30, 15), Some(1_709_209_815));
+        assert_eq!(utc_timestamp(2023, 2, 29, 0, 0, 0), None);
+        assert_eq!(utc_timestamp(2023, 13, 1, 0, 0, 0), None);
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_utc(1_709_209_815), "2024-02-29 12:30:15 UTC");
+    }
+
+    #[test]
+    fn test_printable_rejects_c1_control() {
+        assert!(!is_printable("\u{80}"));
+        assert!(!is_printable("\u{9f}"));
+    }
+
+    #[test]
+    fn test_password_prompt_through_platform() {
+        use crate::platform::{with_platform, MemoryPlatform};
+
+        let KeyPair { sk, .. } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let platform = MemoryPlatform::new(0).with_password("secret");
+        let files = platform.clone();
+        with_platform(platform, || {
+            let protected = protect_secret_key(&sk, false, false).unwrap();
+            files.write_file("/key", protected.to_box(None).unwrap().into_string());
+            assert_eq!(load_secret_key("/key", &Passphrase::Prompt).unwrap(), sk);
+        });
+        // Without a terminal, prompting fails instead of blocking
+        with_platform(MemoryPlatform::new(0), || {
+            assert!(protect_secret_key(&sk, false, false).is_err());
+        });
+    }
+}
@@ -0,0 +1,69 @@
+//! The command layer of `rsign`, usable as a library.
+//!
+//! Every `rsign` command is implemented by a `cmd_*` function that takes
+//! paths and options, and returns a structured result instead of printing.
+//! The `rsign` binary only parses the command line and reports the results.
+//!
+//! ```no_run
+//! use rsign2::{cmd_sign, cmd_verify, DataSource, Passphrase, PublicKey, SignOptions, VerifyOptions};
+//! use std::path::Path;
+//!
+//! # fn main() -> rsign2::Result<()> {
+//! let data = Path::new("release.tar.gz");
+//! let signed = cmd_sign(
+//!     "rsign.key",
+//!     "release.tar.gz.minisig",
+//!     DataSource::File(data),
+//!     &SignOptions {
+//!         passphrase: Passphrase::Empty,
+//!         ..Default::default()
+//!     },
+//! )?;
+//! println!("signed with key {}", signed.key_id);
+//!
+//! let pk = PublicKey::from_file("rsign.pub")?;
+//! let verified = cmd_verify(
+//!     &[pk],
+//!     DataSource::File(data),
+//!     "release.tar.gz.minisig",
+//!     &VerifyOptions::default(),
+//!     None,
+//! )?;
+//! println!("{}", verified.signatures[0].trusted_comment);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod bundle;
+pub mod certificate;
+pub mod comment;
+pub mod compat;
+pub mod config;
+pub mod expiry;
+pub mod filename;
+pub mod generate;
+pub mod helpers;
+pub mod keyformats;
+pub mod namespace;
+pub mod platform;
+pub mod policy;
+pub mod prehash;
+pub mod raw;
+pub mod sigfile;
+pub mod sign;
+pub mod signify;
+pub mod ssh;
+pub mod sshsig;
+pub mod state;
+pub mod verify;
+
+pub use minisign;
+pub use minisign::{ErrorKind, KeyPair, PError, PublicKey, Result, SecretKey, SignatureBox};
+
+pub use crate::generate::{cmd_generate, GenerateOptions, GenerateOutcome};
+pub use crate::helpers::Passphrase;
+pub use crate::prehash::DataSource;
+pub use crate::sign::{cmd_countersign, cmd_sign, SignOptions, SignOutcome};
+pub use crate::verify::{
+    cmd_verify, cmd_verify_countersignatures, VerificationReport, VerifiedSignature, VerifyOptions,
+};
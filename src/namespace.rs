@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use minisign::*;
+
+use crate::comment;
+use crate::platform;
+use crate::raw::key_id;
+use crate::sshsig::match_pattern_list;
+
+/// The trusted comment field binding a signature to a namespace.
+pub const NAMESPACE_FIELD: &str = "namespace";
+
+/// The untrusted comment field listing the namespaces a key can be used for.
+pub const NAMESPACES_FIELD: &str = "namespaces";
+
+pub fn check_namespace_name(namespace: &str) -> Result<()> {
+    if namespace.is_empty() || namespace.contains(|c: char| c.is_control() || c == ',') {
+        return Err(PError::new(
+            ErrorKind::Misc,
+            format!("invalid namespace: {namespace:?}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Add the list of allowed namespaces to the untrusted comment of a key box.
+pub fn record_namespaces(key_box: &str, namespaces: &str) -> Result<String> {
+    if namespaces.contains(['\t', '\r', '\n']) {
+        return Err(PError::new(
+            ErrorKind::Misc,
+            "the namespace list can't contain tabs or line breaks",
+        ));
+    }
+    let (first_line, rest) = key_box.split_once('\n').unwrap_or((key_box, ""));
+    Ok(format!(
+        "{first_line}\t{NAMESPACES_FIELD}:{namespaces}\n{rest}"
+    ))
+}
+
+/// The namespaces recorded in the untrusted comment of a key file, if any.
+pub fn key_namespaces<P>(key_path: P) -> Result<Option<String>>
+where
+    P: AsRef<Path>,
+{
+    let key_box = platform::read_to_string(&key_path).map_err(|e| {
+        PError::new(
+            ErrorKind::Io,
+            format!("could not read {}: {}", key_path.as_ref().display(), e),
+        )
+    })?;
+    let first_line = key_box.lines().next().unwrap_or_default();
+    Ok(comment::field(first_line, NAMESPACES_FIELD).map(str::to_string))
+}
+
+/// Reject a namespace that a key isn't allowed to sign for.
+pub fn check_key_namespace(
+    keynum: &[u8],
+    allowed: Option<&str>,
+    namespace: Option<&str>,
+) -> Result<()> {
+    match (allowed, namespace) {
+        (None, _) => Ok(()),
+        (Some(allowed), Some(namespace)) if match_pattern_list(allowed, namespace) => Ok(()),
+        (Some(allowed), namespace) => Err(PError::new(
+            ErrorKind::Verify,
+            format!(
+                "key {} is only allowed to sign for the {} namespaces, not {}",
+                key_id(keynum),
+                allowed,
+                namespace.map_or("signatures without a namespace".to_string(), |ns| {
+                    format!("the {ns} namespace")
+                })
+            ),
+        )),
+    }
+}
+
+/// Namespace requirements for signature verification: the namespace asked
+/// for on the command line, and the namespaces recorded in public key files.
+#[derive(Default)]
+pub struct NamespacePolicy {
+    pub required: Option<String>,
+    pub allowed_by_key: Vec<(PublicKey, String)>,
+}
+
+impl NamespacePolicy {
+    pub fn check(&self, pk: &PublicKey, trusted_comment: &str) -> Result<()> {
+        let namespace = comment::field(trusted_comment, NAMESPACE_FIELD);
+        if let Some(required) = &self.required {
+            if namespace != Some(required.as_str()) {
+                return Err(PError::new(
+                    ErrorKind::Verify,
+                    format!(
+                        "the signature was made for the {} namespace, not {}",
+                        namespace.unwrap_or("default"),
+                        required
+                    ),
+                ));
+            }
+        }
+        let allowed = self
+            .allowed_by_key
+            .iter()
+            .find(|(key, _)| key.to_bytes() == pk.to_bytes())
+            .map(|(_, allowed)| allowed.as_str());
+        check_key_namespace(pk.keynum(), allowed, namespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace_policy() {
+        let KeyPair { pk, .. } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let tc = "timestamp:1\tfile:f\tprehashed\tnamespace:release";
+        let mut policy = NamespacePolicy::default();
+        assert!(policy.check(&pk, tc).is_ok());
+        assert!(policy.check(&pk, "timestamp:1").is_ok());
+        policy.required = Some("config".to_string());
+        assert!(policy.check(&pk, tc).is_err());
+        policy.required = Some("release".to_string());
+        assert!(policy.check(&pk, tc).is_ok());
+        assert!(policy.check(&pk, "timestamp:1").is_err());
+        policy.required = None;
+        policy
+            .allowed_by_key
+            .push((pk.clone(), "config,backup-*".to_string()));
+        assert!(policy.check(&pk, tc).is_err());
+        assert!(policy
+            .check(&pk, "timestamp:1\tnamespace:backup-db")
+            .is_ok());
+        assert!(policy.check(&pk, "timestamp:1").is_err());
+    }
+
+    #[test]
+    fn test_record_namespaces() {
+        let KeyPair { pk, .. } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let key_box = record_namespaces(&pk.to_box().unwrap().into_string(), "a,b").unwrap();
+        assert!(key_box
+            .lines()
+            .next()
+            .unwrap()
+            .ends_with("\tnamespaces:a,b"));
+        assert_eq!(
+            PublicKey::from_box(PublicKeyBox::from_string(&key_box).unwrap())
+                .unwrap()
+                .to_bytes(),
+            pk.to_bytes()
+        );
+    }
+}
@@ -0,0 +1,690 @@
+//! Access to the filesystem, the environment, the clock and the terminal.
+//!
+//! Commands don't use `std::fs`, `std::env` or the system clock directly,
+//! but the `Platform` of the current thread, which is the real system by
+//! default. `with_platform()` runs commands against another implementation,
+//! such as `MemoryPlatform`, for deterministic tests or for sandboxes
+//! without a home directory.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A file opened for reading.
+pub trait FileReader: Read + Seek {}
+
+impl<T: Read + Seek> FileReader for T {}
+
+/// A file opened for writing.
+pub trait FileWriter: Write {
+    /// Flush the file and wait until it is stored.
+    fn sync_all(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl FileWriter for File {
+    fn sync_all(&mut self) -> io::Result<()> {
+        File::sync_all(self)
+    }
+}
+
+pub trait Platform: Send + Sync {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn FileReader>>;
+
+    /// Create a new file, failing if it already exists. `mode` is only used
+    /// on Unix systems.
+    fn create_new(&self, path: &Path, mode: u32) -> io::Result<Box<dyn FileWriter>>;
+
+    /// Create a file, or truncate it if it already exists.
+    fn create_truncate(&self, path: &Path) -> io::Result<Box<dyn FileWriter>>;
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// The paths of the entries of a directory, in no particular order.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove an empty directory.
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Rename a file or a directory.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// The permissions of a file or a directory, as a Unix mode. Other
+    /// systems only tell whether it is read-only, as 0o444 or 0o644.
+    fn mode(&self, path: &Path) -> io::Result<u32>;
+
+    /// Change the permissions of a file or a directory. Other systems than
+    /// Unix only make it read-only if the mode has no write permission.
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()>;
+
+    fn is_file(&self, path: &Path) -> bool;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.is_file(path) || self.is_dir(path)
+    }
+
+    fn env_var(&self, name: &str) -> Option<String>;
+
+    fn home_dir(&self) -> Option<PathBuf>;
+
+    fn current_dir(&self) -> Option<PathBuf>;
+
+    /// The current time, as a UNIX timestamp.
+    fn now(&self) -> u64;
+
+    /// Convert a date and time of the local time zone, given as the UNIX
+    /// timestamp it would have in UTC, to the actual UNIX timestamp.
+    fn local_to_utc(&self, local: u64) -> Option<u64>;
+
+    /// Ask for a password without echoing it. The prompt can span several
+    /// lines, the last one being followed by the answer.
+    fn prompt_password(&self, prompt: &str) -> io::Result<String>;
+}
+
+/// The real filesystem, environment and clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct System;
+
+impl Platform for System {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn FileReader>> {
+        Ok(Box::new(OpenOptions::new().read(true).open(path)?))
+    }
+
+    fn create_new(&self, path: &Path, mode: u32) -> io::Result<Box<dyn FileWriter>> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(mode);
+        #[cfg(not(unix))]
+        let _ = mode;
+        Ok(Box::new(options.open(path)?))
+    }
+
+    fn create_truncate(&self, path: &Path) -> io::Result<Box<dyn FileWriter>> {
+        Ok(Box::new(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?,
+        ))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        DirBuilder::new().recursive(true).create(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    #[cfg(unix)]
+    fn mode(&self, path: &Path) -> io::Result<u32> {
+        Ok(std::fs::metadata(path)?.permissions().mode() & 0o7777)
+    }
+
+    #[cfg(not(unix))]
+    fn mode(&self, path: &Path) -> io::Result<u32> {
+        if std::fs::metadata(path)?.permissions().readonly() {
+            Ok(0o444)
+        } else {
+            Ok(0o644)
+        }
+    }
+
+    #[cfg(unix)]
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        permissions.set_readonly(mode & 0o222 == 0);
+        std::fs::set_permissions(path, permissions)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    #[cfg(any(windows, unix))]
+    fn home_dir(&self) -> Option<PathBuf> {
+        dirs::home_dir()
+    }
+
+    #[cfg(not(any(windows, unix)))]
+    fn home_dir(&self) -> Option<PathBuf> {
+        Some(PathBuf::from("."))
+    }
+
+    fn current_dir(&self) -> Option<PathBuf> {
+        std::env::current_dir().ok()
+    }
+
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock is incorrect")
+            .as_secs()
+    }
+
+    #[cfg(unix)]
+    fn local_to_utc(&self, local: u64) -> Option<u64> {
+        let local = libc::time_t::try_from(local).ok()?;
+        // SAFETY: `tm` is a plain C struct, filled in by `gmtime_r()`
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::gmtime_r(&local, &mut tm) }.is_null() {
+            return None;
+        }
+        // Let the time zone rules decide whether daylight saving time applies
+        tm.tm_isdst = -1;
+        let timestamp = unsafe { libc::mktime(&mut tm) };
+        u64::try_from(timestamp).ok()
+    }
+
+    /// Without access to the time zone, local time is UTC.
+    #[cfg(not(unix))]
+    fn local_to_utc(&self, local: u64) -> Option<u64> {
+        Some(local)
+    }
+
+    #[cfg(any(windows, unix))]
+    fn prompt_password(&self, prompt: &str) -> io::Result<String> {
+        rpassword::prompt_password(prompt)
+    }
+
+    #[cfg(not(any(windows, unix)))]
+    fn prompt_password(&self, prompt: &str) -> io::Result<String> {
+        let mut stderr = io::stderr();
+        stderr.write_all(prompt.as_bytes())?;
+        stderr.flush()?;
+        let mut password = String::new();
+        io::stdin().read_line(&mut password)?;
+        Ok(password.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryFiles {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    dirs: BTreeSet<PathBuf>,
+    /// Modes that differ from the defaults, 0o644 for files and 0o755 for
+    /// directories.
+    modes: BTreeMap<PathBuf, u32>,
+}
+
+/// An in-memory filesystem, with a fixed environment and clock. Clones
+/// share the same files.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryPlatform {
+    fs: Arc<Mutex<MemoryFiles>>,
+    env: BTreeMap<String, String>,
+    home_dir: Option<PathBuf>,
+    current_dir: Option<PathBuf>,
+    now: u64,
+    /// Offset of the local time zone, in seconds east of UTC.
+    utc_offset: i64,
+    password: Option<String>,
+}
+
+struct MemoryFile {
+    fs: Arc<Mutex<MemoryFiles>>,
+    path: PathBuf,
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut fs = self.fs.lock().unwrap();
+        match fs.files.get_mut(&self.path) {
+            Some(content) => content.extend_from_slice(buf),
+            None => return Err(io::ErrorKind::NotFound.into()),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FileWriter for MemoryFile {}
+
+impl MemoryPlatform {
+    /// An empty filesystem, at time `now`, without environment variables,
+    /// home directory or current directory.
+    pub fn new(now: u64) -> MemoryPlatform {
+        MemoryPlatform {
+            now,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_env(mut self, name: &str, value: &str) -> MemoryPlatform {
+        self.env.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_home_dir<P: Into<PathBuf>>(mut self, path: P) -> MemoryPlatform {
+        self.home_dir = Some(path.into());
+        self
+    }
+
+    pub fn with_current_dir<P: Into<PathBuf>>(mut self, path: P) -> MemoryPlatform {
+        self.current_dir = Some(path.into());
+        self
+    }
+
+    /// A local time zone `utc_offset` seconds east of UTC, instead of UTC.
+    pub fn with_utc_offset(mut self, utc_offset: i64) -> MemoryPlatform {
+        self.utc_offset = utc_offset;
+        self
+    }
+
+    /// The answer to every password prompt, which fail otherwise.
+    pub fn with_password(mut self, password: &str) -> MemoryPlatform {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Add a file, and its parent directories.
+    pub fn write_file<P, C>(&self, path: P, content: C)
+    where
+        P: AsRef<Path>,
+        C: AsRef<[u8]>,
+    {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            let _ = self.create_dir_all(parent);
+        }
+        let mut fs = self.fs.lock().unwrap();
+        fs.files
+            .insert(path.to_path_buf(), content.as_ref().to_vec());
+    }
+
+    pub fn read_file<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+        self.fs.lock().unwrap().files.get(path.as_ref()).cloned()
+    }
+
+    fn create(&self, path: &Path, create_new: bool) -> io::Result<Box<dyn FileWriter>> {
+        let mut fs = self.fs.lock().unwrap();
+        if fs.dirs.contains(path) || (create_new && fs.files.contains_key(path)) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() && !fs.dirs.contains(parent) => {
+                return Err(io::ErrorKind::NotFound.into())
+            }
+            _ => {}
+        }
+        fs.files.insert(path.to_path_buf(), vec![]);
+        Ok(Box::new(MemoryFile {
+            fs: self.fs.clone(),
+            path: path.to_path_buf(),
+        }))
+    }
+}
+
+impl Platform for MemoryPlatform {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn FileReader>> {
+        match self.read_file(path) {
+            Some(content) => Ok(Box::new(io::Cursor::new(content))),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn create_new(&self, path: &Path, mode: u32) -> io::Result<Box<dyn FileWriter>> {
+        let writer = self.create(path, true)?;
+        self.set_mode(path, mode)?;
+        Ok(writer)
+    }
+
+    fn create_truncate(&self, path: &Path) -> io::Result<Box<dyn FileWriter>> {
+        self.create(path, false)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut fs = self.fs.lock().unwrap();
+        for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+            if fs.files.contains_key(dir) {
+                return Err(io::ErrorKind::AlreadyExists.into());
+            }
+            fs.dirs.insert(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let fs = self.fs.lock().unwrap();
+        if !fs.dirs.contains(path) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(fs
+            .files
+            .keys()
+            .chain(fs.dirs.iter())
+            .filter(|entry| entry.parent() == Some(path))
+            .cloned()
+            .collect())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut fs = self.fs.lock().unwrap();
+        match fs.files.remove(path) {
+            Some(_) => {
+                fs.modes.remove(path);
+                Ok(())
+            }
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let mut fs = self.fs.lock().unwrap();
+        if !fs.dirs.contains(path) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        if fs
+            .files
+            .keys()
+            .chain(fs.dirs.iter())
+            .any(|entry| entry.parent() == Some(path))
+        {
+            return Err(io::Error::other("directory not empty"));
+        }
+        fs.dirs.remove(path);
+        fs.modes.remove(path);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut fs = self.fs.lock().unwrap();
+        let moved = |path: &PathBuf| {
+            path.strip_prefix(from)
+                .ok()
+                .map(|rest| (path.clone(), to.join(rest)))
+        };
+        if fs.files.contains_key(from) {
+            let content = fs.files.remove(from).unwrap(); // safe to unwrap
+            fs.files.insert(to.to_path_buf(), content);
+        } else if fs.dirs.contains(from) {
+            let files: Vec<_> = fs.files.keys().filter_map(moved).collect();
+            for (src, dst) in files {
+                let content = fs.files.remove(&src).unwrap(); // safe to unwrap
+                fs.files.insert(dst, content);
+            }
+            let dirs: Vec<_> = fs.dirs.iter().filter_map(moved).collect();
+            for (src, dst) in dirs {
+                fs.dirs.remove(&src);
+                fs.dirs.insert(dst);
+            }
+        } else {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        let modes: Vec<_> = fs.modes.keys().filter_map(moved).collect();
+        for (src, dst) in modes {
+            let mode = fs.modes.remove(&src).unwrap(); // safe to unwrap
+            fs.modes.insert(dst, mode);
+        }
+        Ok(())
+    }
+
+    fn mode(&self, path: &Path) -> io::Result<u32> {
+        let fs = self.fs.lock().unwrap();
+        match fs.modes.get(path) {
+            Some(mode) => Ok(*mode),
+            None if fs.files.contains_key(path) => Ok(0o644),
+            None if fs.dirs.contains(path) => Ok(0o755),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        let mut fs = self.fs.lock().unwrap();
+        if !fs.files.contains_key(path) && !fs.dirs.contains(path) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        fs.modes.insert(path.to_path_buf(), mode);
+        Ok(())
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.fs.lock().unwrap().files.contains_key(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.fs.lock().unwrap().dirs.contains(path)
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.env.get(name).cloned()
+    }
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        self.home_dir.clone()
+    }
+
+    fn current_dir(&self) -> Option<PathBuf> {
+        self.current_dir.clone()
+    }
+
+    fn now(&self) -> u64 {
+        self.now
+    }
+
+    fn local_to_utc(&self, local: u64) -> Option<u64> {
+        u64::try_from(i64::try_from(local).ok()? - self.utc_offset).ok()
+    }
+
+    fn prompt_password(&self, _prompt: &str) -> io::Result<String> {
+        self.password.clone().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no terminal to ask for a password")
+        })
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Arc<dyn Platform>> = RefCell::new(Arc::new(System));
+}
+
+/// The platform used by the current thread.
+pub fn current() -> Arc<dyn Platform> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Run `f` with `platform` as the platform of the current thread.
+pub fn with_platform<P, F, T>(platform: P, f: F) -> T
+where
+    P: Platform + 'static,
+    F: FnOnce() -> T,
+{
+    struct Restore(Option<Arc<dyn Platform>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(previous) = self.0.take() {
+                CURRENT.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+    }
+
+    let previous = CURRENT.with(|current| current.replace(Arc::new(platform)));
+    let _restore = Restore(Some(previous));
+    f()
+}
+
+/// Read a whole file, as `std::fs::read()` does.
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let mut content = vec![];
+    current().open(path.as_ref())?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Read a whole file as text, as `std::fs::read_to_string()` does.
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut content = String::new();
+    current()
+        .open(path.as_ref())?
+        .read_to_string(&mut content)?;
+    Ok(content)
+}
+
+pub fn exists<P: AsRef<Path>>(path: P) -> bool {
+    current().exists(path.as_ref())
+}
+
+pub fn is_file<P: AsRef<Path>>(path: P) -> bool {
+    current().is_file(path.as_ref())
+}
+
+pub fn is_dir<P: AsRef<Path>>(path: P) -> bool {
+    current().is_dir(path.as_ref())
+}
+
+pub fn read_dir<P: AsRef<Path>>(path: P) -> io::Result<Vec<PathBuf>> {
+    current().read_dir(path.as_ref())
+}
+
+pub fn remove_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    current().remove_file(path.as_ref())
+}
+
+pub fn remove_dir<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    current().remove_dir(path.as_ref())
+}
+
+pub fn mode<P: AsRef<Path>>(path: P) -> io::Result<u32> {
+    current().mode(path.as_ref())
+}
+
+pub fn set_mode<P: AsRef<Path>>(path: P, mode: u32) -> io::Result<()> {
+    current().set_mode(path.as_ref(), mode)
+}
+
+pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<()> {
+    current().rename(from.as_ref(), to.as_ref())
+}
+
+pub fn env_var(name: &str) -> Option<String> {
+    current().env_var(name)
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    current().home_dir()
+}
+
+pub fn current_dir() -> Option<PathBuf> {
+    current().current_dir()
+}
+
+pub fn prompt_password(prompt: &str) -> io::Result<String> {
+    current().prompt_password(prompt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_platform() {
+        let platform = MemoryPlatform::new(1556193335).with_env("NAME", "value");
+        platform.write_file("/a/b", "content");
+        let files = platform.clone();
+        with_platform(platform, || {
+            assert_eq!(current().now(), 1556193335);
+            assert_eq!(env_var("NAME").as_deref(), Some("value"));
+            assert!(is_dir("/a") && is_file("/a/b"));
+            assert_eq!(read_to_string("/a/b").unwrap(), "content");
+            assert!(current().create_new(Path::new("/a/b"), 0o644).is_err());
+            assert!(current().create_new(Path::new("/c/d"), 0o644).is_err());
+            let mut writer = current().create_truncate(Path::new("/a/b")).unwrap();
+            writer.write_all(b"new").unwrap();
+            rename("/a/b", "/a/c").unwrap();
+            assert!(!exists("/a/b"));
+        });
+        assert_eq!(files.read_file("/a/c").unwrap(), b"new");
+        assert!(current().now() > 1556193335);
+    }
+
+    #[test]
+    fn test_commands_in_memory() {
+        use crate::generate::{cmd_generate, GenerateOptions};
+        use crate::prehash::DataSource;
+        use crate::sign::{cmd_sign, SignOptions};
+        use crate::verify::{cmd_verify, VerifyOptions};
+
+        let platform = MemoryPlatform::new(1556193335).with_home_dir("/home/user");
+        platform.write_file("/data/file", "data");
+        let files = platform.clone();
+        let report = with_platform(platform, || {
+            let sk_path = crate::config::create_sk_path_or_default(None, false).unwrap();
+            assert_eq!(sk_path, Path::new("/home/user/.config/rsign/rsign.key"));
+            let generated = cmd_generate(
+                "/data/rsign.pub",
+                &sk_path,
+                &GenerateOptions {
+                    unencrypted: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let data = DataSource::File(Path::new("/data/file"));
+            cmd_sign(
+                &sk_path,
+                "/data/file.minisig",
+                data,
+                &SignOptions::default(),
+            )
+            .unwrap();
+            cmd_verify(
+                &[generated.key_pair.pk],
+                data,
+                "/data/file.minisig",
+                &VerifyOptions::default(),
+                None,
+            )
+            .unwrap()
+        });
+        assert_eq!(report.signatures[0].fields["timestamp"], "1556193335");
+        assert!(files.read_file("/data/file.minisig").is_some());
+        assert!(!Path::new("/data/file.minisig").exists());
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use minisign::*;
+use serde::Deserialize;
+
+use crate::comment;
+use crate::expiry::parse_duration;
+use crate::platform;
+use crate::raw::key_id;
+use crate::sshsig::match_pattern_list;
+
+/// A verification rule. Rules without a `path` apply to every file, the
+/// others to files whose path or name matches one of their patterns, and
+/// fail when there is no file to match, such as with a prehash. Paths are
+/// matched relative to the root of the policy, or as absolute paths.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: Option<String>,
+    pub path: Option<String>,
+    /// IDs of the keys allowed to sign, either the trusted key or the
+    /// subkey it certified that actually signed.
+    pub keys: Option<Vec<String>>,
+    #[serde(default)]
+    pub require_fields: Vec<String>,
+    /// Trusted comment fields whose value must match a pattern list.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    pub max_age: Option<String>,
+    pub allow_legacy: Option<bool>,
+}
+
+/// A set of rules evaluated after a signature has been cryptographically
+/// verified. Every matching rule must pass.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+    /// Directory that relative path patterns start from, the current
+    /// directory by default.
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+/// Make a path absolute and remove its `.` and `..` components, without
+/// accessing the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = match platform::current_dir() {
+        Some(dir) if path.is_relative() => normalize_path(&dir),
+        _ => PathBuf::new(),
+    };
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The components of a path separated by `/`, the separator of patterns.
+fn path_string(path: &Path) -> String {
+    let names: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    let prefix = if path.has_root() { "/" } else { "" };
+    format!("{}{}", prefix, names.join("/"))
+}
+
+/// Remove the `./` prefixes of relative patterns.
+fn normalize_patterns(patterns: &str) -> String {
+    let patterns: Vec<String> = patterns
+        .split(',')
+        .map(|pattern| {
+            let (negation, mut pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => ("!", pattern),
+                None => ("", pattern),
+            };
+            while let Some(rest) = pattern.strip_prefix("./") {
+                pattern = rest;
+            }
+            format!("{negation}{pattern}")
+        })
+        .collect();
+    patterns.join(",")
+}
+
+impl Rule {
+    fn display_name(&self, index: usize) -> String {
+        match &self.name {
+            Some(name) => format!("\"{name}\""),
+            None => format!("#{}", index + 1),
+        }
+    }
+
+    fn applies_to(&self, data_path: Option<&Path>, root: Option<&Path>) -> bool {
+        let patterns = match &self.path {
+            None => return true,
+            Some(patterns) => normalize_patterns(patterns),
+        };
+        let data_path = match data_path {
+            None => return false,
+            Some(data_path) => normalize_path(data_path),
+        };
+        let root = match root {
+            Some(root) => normalize_path(root),
+            None => normalize_path(Path::new(".")),
+        };
+        let relative = data_path.strip_prefix(&root).ok().map(path_string);
+        match_pattern_list(&patterns, &path_string(&data_path))
+            || relative.is_some_and(|relative| match_pattern_list(&patterns, &relative))
+            || data_path
+                .file_name()
+                .is_some_and(|name| match_pattern_list(&patterns, &name.to_string_lossy()))
+    }
+
+    /// The reasons why a signature doesn't satisfy this rule.
+    fn failures(
+        &self,
+        pk: &PublicKey,
+        signer: &PublicKey,
+        signature_box: &SignatureBox,
+        trusted_comment: &str,
+        at: u64,
+    ) -> Vec<String> {
+        let mut failures = vec![];
+        if let Some(keys) = &self.keys {
+            let signers = [key_id(pk.keynum()), key_id(signer.keynum())];
+            if !keys.iter().any(|key| {
+                signers
+                    .iter()
+                    .any(|signer| signer.eq_ignore_ascii_case(key))
+            }) {
+                failures.push(format!("key {} is not allowed", signers[0]));
+            }
+        }
+        for name in &self.require_fields {
+            if comment::field(trusted_comment, name).is_none() {
+                failures.push(format!("the trusted comment has no {name} field"));
+            }
+        }
+        for (name, patterns) in &self.fields {
+            match comment::field(trusted_comment, name) {
+                Some(value) if match_pattern_list(patterns, value) => {}
+                Some(value) => failures.push(format!(
+                    "the {name} field is {value}, which doesn't match {patterns}"
+                )),
+                None => failures.push(format!("the trusted comment has no {name} field")),
+            }
+        }
+        if let Some(max_age) = &self.max_age {
+            let max_age_secs = parse_duration(max_age).unwrap_or_default();
+            match comment::timestamp(trusted_comment) {
+                Some(timestamp) if at.saturating_sub(timestamp) <= max_age_secs => {}
+                Some(_) => failures.push(format!("the signature is older than {max_age}")),
+                None => failures.push("the signature has no timestamp".to_string()),
+            }
+        }
+        if self.allow_legacy == Some(false) && !signature_box.is_prehashed() {
+            failures.push("legacy signatures are not allowed".to_string());
+        }
+        failures
+    }
+}
+
+impl Policy {
+    pub fn from_string(s: &str) -> Result<Policy> {
+        let policy: Policy =
+            toml::from_str(s).map_err(|e| PError::new(ErrorKind::Encoding, e.to_string()))?;
+        for (i, rule) in policy.rules.iter().enumerate() {
+            if let Some(max_age) = &rule.max_age {
+                if parse_duration(max_age).is_none() {
+                    return Err(PError::new(
+                        ErrorKind::Encoding,
+                        format!(
+                            "rule {}: invalid max_age: {}",
+                            rule.display_name(i),
+                            max_age
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(policy)
+    }
+
+    pub fn from_file<P>(path: P) -> Result<Policy>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let s = platform::read_to_string(path).map_err(|e| {
+            PError::new(
+                ErrorKind::Io,
+                format!("could not read policy file {}: {}", path.display(), e),
+            )
+        })?;
+        Policy::from_string(&s).map_err(|e| {
+            PError::new(
+                ErrorKind::Encoding,
+                format!("invalid policy file {}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Whether a rule applying to the file explicitly accepts legacy
+    /// signatures.
+    pub fn allows_legacy(&self, data_path: Option<&Path>) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.allow_legacy == Some(true) && rule.applies_to(data_path, self.root.as_deref())
+        })
+    }
+
+    /// Evaluate the rules applying to a verified signature, and list the
+    /// ones that failed. `pk` is the trusted key, and `signer` the key that
+    /// made the signature, either `pk` or a subkey it certified.
+    pub fn check(
+        &self,
+        data_path: Option<&Path>,
+        pk: &PublicKey,
+        signer: &PublicKey,
+        signature_box: &SignatureBox,
+        trusted_comment: &str,
+        at: u64,
+    ) -> Result<()> {
+        let mut report = String::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if let (Some(patterns), None) = (&rule.path, data_path) {
+                report.push_str(&format!(
+                    "\n  rule {}: only applies to {}, which requires verifying the file itself",
+                    rule.display_name(i),
+                    patterns
+                ));
+                continue;
+            }
+            if !rule.applies_to(data_path, self.root.as_deref()) {
+                continue;
+            }
+            for failure in rule.failures(pk, signer, signature_box, trusted_comment, at) {
+                report.push_str(&format!("\n  rule {}: {}", rule.display_name(i), failure));
+            }
+        }
+        if !report.is_empty() {
+            return Err(PError::new(
+                ErrorKind::Verify,
+                format!("Signature rejected by policy:{report}"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_rules() {
+        let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let policy = Policy::from_string(&format!(
+            r#"
+            [[rule]]
+            max_age = "1d"
+
+            [[rule]]
+            name = "releases"
+            path = "releases/*"
+            keys = ["{}"]
+            require_fields = ["version"]
+            fields = {{ namespace = "release" }}
+            "#,
+            key_id(pk.keynum())
+        ))
+        .unwrap();
+        let tc = "timestamp:1000\tnamespace:release";
+        let signature_box = sign(Some(&pk), &sk, &b"data"[..], Some(tc), None).unwrap();
+        let path = Path::new("releases/app.tar.gz");
+        assert!(policy
+            .check(Some(Path::new("app")), &pk, &pk, &signature_box, tc, 2000)
+            .is_ok());
+        // Path-scoped rules can't be skipped when there is no path
+        let err = policy
+            .check(None, &pk, &pk, &signature_box, tc, 2000)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("rule \"releases\": only applies to releases/*"));
+        let err = policy
+            .check(Some(path), &pk, &pk, &signature_box, tc, 100_000)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("rule #1: the signature is older than 1d"));
+        assert!(err.contains("rule \"releases\": the trusted comment has no version field"));
+        assert!(Policy::from_string("[[rule]]\nmax_age = \"forever\"").is_err());
+        assert!(Policy::from_string("[[rule]]\nmax_agee = \"1d\"").is_err());
+    }
+
+    #[test]
+    fn test_path_spellings() {
+        use crate::platform::{with_platform, MemoryPlatform};
+
+        let policy = Policy::from_string(
+            r#"
+            [[rule]]
+            path = "./releases/*"
+            allow_legacy = true
+            "#,
+        )
+        .unwrap();
+        let platform = MemoryPlatform::new(0).with_current_dir("/work/releases");
+        with_platform(platform, || {
+            let policy = Policy {
+                root: Some(PathBuf::from("/work")),
+                ..policy
+            };
+            for path in [
+                "app.tar.gz",
+                "./app.tar.gz",
+                "../releases/./app.tar.gz",
+                "/work/releases/app.tar.gz",
+                "/work/./releases/app.tar.gz",
+            ]
+            .iter()
+            {
+                assert!(policy.allows_legacy(Some(Path::new(path))), "{}", path);
+            }
+            for path in [
+                "../app.tar.gz",
+                "/other/releases/app.tar.gz",
+                "/work/app.tar.gz",
+            ]
+            .iter()
+            {
+                assert!(!policy.allows_legacy(Some(Path::new(path))), "{}", path);
+            }
+            let policy =
+                Policy::from_string("[[rule]]\npath = \"/work/releases/*\"\nallow_legacy = true")
+                    .unwrap();
+            assert!(policy.allows_legacy(Some(Path::new("./app.tar.gz"))));
+            assert!(!policy.allows_legacy(Some(Path::new("../app.tar.gz"))));
+        });
+    }
+
+    #[test]
+    fn test_keys_match_the_verified_signer() {
+        let root = KeyPair::generate_unencrypted_keypair().unwrap();
+        let subkey = KeyPair::generate_unencrypted_keypair().unwrap();
+        let other = KeyPair::generate_unencrypted_keypair().unwrap();
+        let policy = Policy::from_string(&format!(
+            "[[rule]]\nkeys = [\"{}\"]",
+            key_id(other.pk.keynum())
+        ))
+        .unwrap();
+        let tc = "timestamp:1000";
+        let signature_box = sign(None, &other.sk, &b"data"[..], Some(tc), None).unwrap();
+        // The key ID of the signature itself isn't authenticated
+        assert!(policy
+            .check(None, &root.pk, &subkey.pk, &signature_box, tc, 1000)
+            .is_err());
+        assert!(policy
+            .check(None, &root.pk, &other.pk, &signature_box, tc, 1000)
+            .is_ok());
+        assert!(policy
+            .check(None, &other.pk, &subkey.pk, &signature_box, tc, 1000)
+            .is_ok());
+    }
+}
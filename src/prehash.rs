@@ -0,0 +1,272 @@
+use std::io::Read;
+use std::path::Path;
+
+use blake2::{Blake2b512, Digest};
+use ct_codecs::{Base64, Decoder, Encoder, Hex};
+use minisign::*;
+
+use crate::helpers::open_data_file;
+use crate::raw::{
+    key_id, sign_raw, signature_box_from_parts, signature_parts, verify_raw, SignatureParts,
+};
+
+pub const PREHASH_BYTES: usize = 64;
+
+const SIGALG_PREHASHED: [u8; 2] = *b"ED";
+const SIGALG_LEGACY: [u8; 2] = *b"Ed";
+
+/// Where the signed data comes from: a file, or a digest computed beforehand.
+#[derive(Clone, Copy)]
+pub enum DataSource<'a> {
+    File(&'a Path),
+    Prehash(&'a [u8]),
+    /// No data: only the trusted comments are verified, and nothing can be
+    /// signed.
+    CommentOnly,
+}
+
+/// Compute the BLAKE2b-512 digest that `minisign` signs for prehashed signatures.
+pub fn prehash<R>(mut data_reader: R) -> Result<Vec<u8>>
+where
+    R: Read,
+{
+    let mut state = Blake2b512::new();
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let len = data_reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        state.update(&buf[..len]);
+    }
+    Ok(state.finalize().to_vec())
+}
+
+pub fn encode_prehash(prehash: &[u8], base64: bool) -> Result<String> {
+    if base64 {
+        Ok(Base64::encode_to_string(prehash)?)
+    } else {
+        Ok(Hex::encode_to_string(prehash)?)
+    }
+}
+
+pub fn decode_prehash(s: &str) -> Result<Vec<u8>> {
+    let prehash = Hex::decode_to_vec(s.trim(), None).map_err(|_| {
+        PError::new(
+            ErrorKind::Encoding,
+            "prehash must be a hex-encoded BLAKE2b-512 digest",
+        )
+    })?;
+    if prehash.len() != PREHASH_BYTES {
+        return Err(PError::new(
+            ErrorKind::Encoding,
+            format!(
+                "prehash must be {} bytes long, got {}",
+                PREHASH_BYTES,
+                prehash.len()
+            ),
+        ));
+    }
+    Ok(prehash)
+}
+
+/// Sign a digest computed with `prehash()`, without access to the original data.
+pub fn sign_prehashed(
+    pk: Option<&PublicKey>,
+    sk: &SecretKey,
+    prehash: &[u8],
+    trusted_comment: &str,
+    untrusted_comment: Option<&str>,
+) -> Result<SignatureBox> {
+    sign_message(
+        SIGALG_PREHASHED,
+        pk,
+        sk,
+        prehash,
+        trusted_comment,
+        untrusted_comment,
+    )
+}
+
+/// Create a legacy signature, computed over the whole content of a file
+/// instead of its prehash. Only useful for verifiers that predate prehashing.
+pub fn sign_legacy(
+    pk: Option<&PublicKey>,
+    sk: &SecretKey,
+    data: &[u8],
+    trusted_comment: &str,
+    untrusted_comment: Option<&str>,
+) -> Result<SignatureBox> {
+    sign_message(
+        SIGALG_LEGACY,
+        pk,
+        sk,
+        data,
+        trusted_comment,
+        untrusted_comment,
+    )
+}
+
+fn sign_message(
+    sig_alg: [u8; 2],
+    pk: Option<&PublicKey>,
+    sk: &SecretKey,
+    message: &[u8],
+    trusted_comment: &str,
+    untrusted_comment: Option<&str>,
+) -> Result<SignatureBox> {
+    let signature = sign_raw(sk, message)?;
+    let mut sig_and_trusted_comment = signature.to_vec();
+    sig_and_trusted_comment.extend_from_slice(trusted_comment.as_bytes());
+    let global_signature = sign_raw(sk, &sig_and_trusted_comment)?;
+    if let Some(pk) = pk {
+        if !verify_raw(pk, &sig_and_trusted_comment, &global_signature) {
+            return Err(PError::new(
+                ErrorKind::Verify,
+                format!(
+                    "Could not verify signature with the provided public key ID: {}",
+                    key_id(pk.keynum())
+                ),
+            ));
+        }
+    }
+    let mut keynum = [0u8; 8];
+    keynum.copy_from_slice(sk.keynum());
+    let parts = SignatureParts {
+        sig_alg,
+        keynum,
+        signature,
+        trusted_comment: trusted_comment.to_string(),
+        global_signature,
+    };
+    signature_box_from_parts(&parts, untrusted_comment.unwrap_or(DEFAULT_COMMENT))
+}
+
+/// Verify a prehashed signature against a digest instead of the original data.
+pub fn verify_prehashed(
+    pk: &PublicKey,
+    signature_box: &SignatureBox,
+    prehash: &[u8],
+) -> Result<()> {
+    let parts = signature_parts(signature_box)?;
+    check_key_id(pk, &parts)?;
+    if !signature_box.is_prehashed() {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            "Legacy signatures cover the whole file and can't be verified against a prehash",
+        ));
+    }
+    if !verify_raw(pk, prehash, &parts.signature) {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            "Signature verification failed",
+        ));
+    }
+    check_comment_signature(pk, &parts)
+}
+
+/// Verify the global signature, which covers the signature of the data and
+/// the trusted comment, without reading the data.
+pub fn verify_comment_signature(pk: &PublicKey, signature_box: &SignatureBox) -> Result<()> {
+    let parts = signature_parts(signature_box)?;
+    check_key_id(pk, &parts)?;
+    check_comment_signature(pk, &parts)
+}
+
+fn check_key_id(pk: &PublicKey, parts: &SignatureParts) -> Result<()> {
+    if parts.keynum != pk.keynum() {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            format!(
+                "Signature key id: {} is different from public key: {}",
+                key_id(&parts.keynum),
+                key_id(pk.keynum())
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn check_comment_signature(pk: &PublicKey, parts: &SignatureParts) -> Result<()> {
+    let mut sig_and_trusted_comment = parts.signature.to_vec();
+    sig_and_trusted_comment.extend_from_slice(parts.trusted_comment.as_bytes());
+    if !verify_raw(pk, &sig_and_trusted_comment, &parts.global_signature) {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            "Comment signature verification failed",
+        ));
+    }
+    Ok(())
+}
+
+/// Compute the digest of a file, as accepted by `sign --prehash`.
+pub fn cmd_hash<P>(data_path: P, base64: bool) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let data_reader = open_data_file(&data_path).map_err(|err| {
+        PError::new(
+            ErrorKind::Io,
+            format!(
+                "could not read data file {}: {}",
+                data_path.as_ref().display(),
+                err
+            ),
+        )
+    })?;
+    encode_prehash(&prehash(data_reader)?, base64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prehash_round_trip() {
+        let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let data = b"some data to sign";
+        let signature_box = sign(Some(&pk), &sk, &data[..], Some("comment"), None).unwrap();
+        let digest = prehash(&data[..]).unwrap();
+        verify_prehashed(&pk, &signature_box, &digest).unwrap();
+        assert!(verify_prehashed(&pk, &signature_box, &[0u8; PREHASH_BYTES]).is_err());
+    }
+
+    #[test]
+    fn test_verify_comment_signature() {
+        let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let signature_box = sign(Some(&pk), &sk, &b"data"[..], Some("version:1"), None).unwrap();
+        verify_comment_signature(&pk, &signature_box).unwrap();
+        let tampered = signature_box.to_string().replace("version:1", "version:2");
+        let tampered = SignatureBox::from_string(&tampered).unwrap();
+        assert!(verify_comment_signature(&pk, &tampered).is_err());
+        let other = KeyPair::generate_unencrypted_keypair().unwrap().pk;
+        assert!(verify_comment_signature(&other, &signature_box).is_err());
+    }
+
+    #[test]
+    fn test_sign_prehashed_verifies_with_data() {
+        let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let data = b"some data to sign";
+        let digest = prehash(&data[..]).unwrap();
+        let signature_box = sign_prehashed(Some(&pk), &sk, &digest, "comment", None).unwrap();
+        let signature_box = SignatureBox::from_string(&signature_box.to_string()).unwrap();
+        verify(
+            &pk,
+            &signature_box,
+            std::io::Cursor::new(&data[..]),
+            true,
+            false,
+            false,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_decode_prehash() {
+        let digest = prehash(&b""[..]).unwrap();
+        let encoded = encode_prehash(&digest, false).unwrap();
+        assert_eq!(decode_prehash(&encoded).unwrap(), digest);
+        assert!(decode_prehash("abcd").is_err());
+        assert!(decode_prehash("not hex").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::cmp;
+
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use ct_codecs::{Base64, Decoder, Encoder};
+use minisign::*;
+
+const SIGALG_LEN: usize = 2;
+const KEYNUM_LEN: usize = 8;
+const SK_KDF_ALG_OFFSET: usize = 2;
+const SK_CHK_ALG_OFFSET: usize = 4;
+const SK_KDF_SALT_OFFSET: usize = 6;
+const SK_KDF_OPSLIMIT_OFFSET: usize = 38;
+const SK_KDF_MEMLIMIT_OFFSET: usize = 46;
+const SK_KEYNUM_OFFSET: usize = 54;
+const SK_CHK_BYTES: usize = 32;
+const SK_TOTAL_BYTES: usize = SK_KEYNUM_OFFSET + KEYNUM_LEN + SECRET_KEY_BYTES + SK_CHK_BYTES;
+
+const SIGALG: [u8; 2] = *b"Ed";
+const CHK_ALG: [u8; 2] = *b"B2";
+const KDF_ALG: [u8; 2] = *b"Sc";
+const KDF_SALT_BYTES: usize = 32;
+const KDF_OPSLIMIT: u64 = 1_048_576;
+const KDF_MEMLIMIT: u64 = 33_554_432;
+
+pub const PUBLIC_KEY_BYTES: usize = 32;
+pub const SECRET_KEY_BYTES: usize = 64;
+pub const SIGNATURE_BYTES: usize = 64;
+
+/// Raw components of a `SignatureBox`.
+pub struct SignatureParts {
+    pub sig_alg: [u8; SIGALG_LEN],
+    pub keynum: [u8; KEYNUM_LEN],
+    pub signature: [u8; SIGNATURE_BYTES],
+    pub trusted_comment: String,
+    pub global_signature: [u8; SIGNATURE_BYTES],
+}
+
+pub fn key_id(keynum: &[u8]) -> String {
+    let mut x = [0u8; KEYNUM_LEN];
+    x.copy_from_slice(&keynum[..KEYNUM_LEN]);
+    format!("{:016X}", u64::from_le_bytes(x))
+}
+
+/// Parse a key identifier, as printed by `key_id()`.
+pub fn parse_key_id(s: &str) -> Result<[u8; KEYNUM_LEN]> {
+    let s = s.trim();
+    if s.len() != KEYNUM_LEN * 2 || !s.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(PError::new(
+            ErrorKind::Encoding,
+            "a key ID must be 16 hexadecimal characters",
+        ));
+    }
+    let keynum = u64::from_str_radix(s, 16).unwrap(); // safe to unwrap
+    Ok(keynum.to_le_bytes())
+}
+
+pub fn random_keynum() -> Result<[u8; KEYNUM_LEN]> {
+    let mut keynum = [0u8; KEYNUM_LEN];
+    getrandom::fill(&mut keynum)?;
+    Ok(keynum)
+}
+
+pub fn public_key_bytes(pk: &PublicKey) -> [u8; PUBLIC_KEY_BYTES] {
+    let mut raw = [0u8; PUBLIC_KEY_BYTES];
+    raw.copy_from_slice(&pk.to_bytes()[SIGALG_LEN + KEYNUM_LEN..]);
+    raw
+}
+
+pub fn secret_key_bytes(sk: &SecretKey) -> [u8; SECRET_KEY_BYTES] {
+    let offset = SK_KEYNUM_OFFSET + KEYNUM_LEN;
+    let mut raw = [0u8; SECRET_KEY_BYTES];
+    raw.copy_from_slice(&sk.to_bytes()[offset..offset + SECRET_KEY_BYTES]);
+    raw
+}
+
+pub fn public_key_from_raw(
+    keynum: &[u8; KEYNUM_LEN],
+    raw: &[u8; PUBLIC_KEY_BYTES],
+) -> Result<PublicKey> {
+    let mut bytes = Vec::with_capacity(SIGALG_LEN + KEYNUM_LEN + PUBLIC_KEY_BYTES);
+    bytes.extend_from_slice(&SIGALG);
+    bytes.extend_from_slice(keynum);
+    bytes.extend_from_slice(raw);
+    PublicKey::from_bytes(&bytes)
+}
+
+/// Build an unencrypted minisign secret key from a key identifier and a raw
+/// Ed25519 secret key (seed followed by the public key).
+pub fn secret_key_from_raw(
+    keynum: &[u8; KEYNUM_LEN],
+    raw: &[u8; SECRET_KEY_BYTES],
+) -> Result<SecretKey> {
+    let mut bytes = vec![0u8; SK_TOTAL_BYTES];
+    bytes[..SIGALG_LEN].copy_from_slice(&SIGALG);
+    bytes[SK_CHK_ALG_OFFSET..SK_KDF_SALT_OFFSET].copy_from_slice(&CHK_ALG);
+    let offset = SK_KEYNUM_OFFSET;
+    bytes[offset..offset + KEYNUM_LEN].copy_from_slice(keynum);
+    let offset = offset + KEYNUM_LEN;
+    bytes[offset..offset + SECRET_KEY_BYTES].copy_from_slice(raw);
+    let offset = offset + SECRET_KEY_BYTES;
+    let mut state = Blake2b::<U32>::new();
+    state.update(SIGALG);
+    state.update(keynum);
+    state.update(raw);
+    bytes[offset..].copy_from_slice(&state.finalize());
+    SecretKey::from_bytes(&bytes)
+}
+
+fn scrypt_params(memlimit: u64, opslimit: u64) -> Result<scrypt::Params> {
+    let opslimit = cmp::max(32768, opslimit);
+    let r = 8u32;
+    let mut n_log2 = 1u8;
+    let p;
+    if opslimit < memlimit / 32 {
+        p = 1;
+        let maxn = opslimit / (u64::from(r) * 4);
+        while n_log2 < 63 && 1u64 << n_log2 <= maxn / 2 {
+            n_log2 += 1;
+        }
+    } else {
+        let maxn = memlimit / (u64::from(r) * 128);
+        while n_log2 < 63 && 1u64 << n_log2 <= maxn / 2 {
+            n_log2 += 1;
+        }
+        let maxrp = cmp::min(0x3fff_ffff_u32, ((opslimit / 4) / (1u64 << n_log2)) as u32);
+        p = maxrp / r;
+    }
+    scrypt::Params::new(n_log2, r, p, scrypt::Params::RECOMMENDED_LEN)
+        .map_err(|e| PError::new(ErrorKind::KDF, e.to_string()))
+}
+
+/// Encrypt an unencrypted secret key with a password, the same way `minisign` does.
+pub fn encrypt_secret_key(sk: &SecretKey, password: &str) -> Result<SecretKey> {
+    let mut bytes = sk.to_bytes();
+    if bytes[SK_KDF_ALG_OFFSET..SK_KDF_ALG_OFFSET + SIGALG_LEN] != [0u8; 2] {
+        return Err(PError::new(
+            ErrorKind::EncryptedKey,
+            "secret key is already encrypted",
+        ));
+    }
+    let mut salt = [0u8; KDF_SALT_BYTES];
+    getrandom::fill(&mut salt)?;
+    bytes[SK_KDF_ALG_OFFSET..SK_KDF_ALG_OFFSET + SIGALG_LEN].copy_from_slice(&KDF_ALG);
+    bytes[SK_KDF_SALT_OFFSET..SK_KDF_OPSLIMIT_OFFSET].copy_from_slice(&salt);
+    bytes[SK_KDF_OPSLIMIT_OFFSET..SK_KDF_MEMLIMIT_OFFSET]
+        .copy_from_slice(&KDF_OPSLIMIT.to_le_bytes());
+    bytes[SK_KDF_MEMLIMIT_OFFSET..SK_KEYNUM_OFFSET].copy_from_slice(&KDF_MEMLIMIT.to_le_bytes());
+    let mut stream = [0u8; SK_TOTAL_BYTES - SK_KEYNUM_OFFSET];
+    let params = scrypt_params(KDF_MEMLIMIT, KDF_OPSLIMIT)?;
+    scrypt::scrypt(password.as_bytes(), &salt, &params, &mut stream)
+        .map_err(|e| PError::new(ErrorKind::KDF, e.to_string()))?;
+    for (byte, stream) in bytes[SK_KEYNUM_OFFSET..].iter_mut().zip(stream.iter()) {
+        *byte ^= *stream;
+    }
+    SecretKey::from_bytes(&bytes)
+}
+
+pub fn sign_raw(sk: &SecretKey, message: &[u8]) -> Result<[u8; SIGNATURE_BYTES]> {
+    if sk.is_encrypted() {
+        return Err(PError::new(
+            ErrorKind::EncryptedKey,
+            "Cannot sign with an encrypted secret key",
+        ));
+    }
+    let sk = ed25519_compact::SecretKey::from_slice(&secret_key_bytes(sk))
+        .map_err(|e| PError::new(ErrorKind::Sign, e))?;
+    let signature = sk.sign(message, Some(ed25519_compact::Noise::generate()));
+    Ok(*signature)
+}
+
+pub fn verify_raw(pk: &PublicKey, message: &[u8], signature: &[u8]) -> bool {
+    let pk = match ed25519_compact::PublicKey::from_slice(&public_key_bytes(pk)) {
+        Ok(pk) => pk,
+        Err(_) => return false,
+    };
+    let signature = match ed25519_compact::Signature::from_slice(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    pk.verify(message, &signature).is_ok()
+}
+
+pub fn signature_parts(signature_box: &SignatureBox) -> Result<SignatureParts> {
+    let bones: SignatureBones = signature_box.clone().into();
+    let bones = bones.to_bytes();
+    let mut sig_alg = [0u8; SIGALG_LEN];
+    let mut keynum = [0u8; KEYNUM_LEN];
+    let mut signature = [0u8; SIGNATURE_BYTES];
+    sig_alg.copy_from_slice(&bones[..SIGALG_LEN]);
+    keynum.copy_from_slice(&bones[SIGALG_LEN..SIGALG_LEN + KEYNUM_LEN]);
+    signature.copy_from_slice(&bones[SIGALG_LEN + KEYNUM_LEN..]);
+    let encoded_global_sig = signature_box
+        .to_string()
+        .lines()
+        .nth(3)
+        .map(|line| line.trim().to_string())
+        .ok_or_else(|| PError::new(ErrorKind::Encoding, "Missing global signature"))?;
+    let decoded_global_sig = Base64::decode_to_vec(&encoded_global_sig, None)?;
+    if decoded_global_sig.len() != SIGNATURE_BYTES {
+        return Err(PError::new(
+            ErrorKind::Encoding,
+            "invalid global signature length",
+        ));
+    }
+    let mut global_signature = [0u8; SIGNATURE_BYTES];
+    global_signature.copy_from_slice(&decoded_global_sig);
+    Ok(SignatureParts {
+        sig_alg,
+        keynum,
+        signature,
+        trusted_comment: signature_box.trusted_comment()?,
+        global_signature,
+    })
+}
+
+/// Parse a signature box, rejecting global signatures of the wrong size,
+/// which `minisign` doesn't check before verifying them.
+pub fn parse_signature_box(s: &str) -> Result<SignatureBox> {
+    let signature_box = SignatureBox::from_string(s)?;
+    signature_parts(&signature_box)?;
+    Ok(signature_box)
+}
+
+pub fn signature_box_from_parts(
+    parts: &SignatureParts,
+    untrusted_comment: &str,
+) -> Result<SignatureBox> {
+    let mut sig = Vec::with_capacity(SIGALG_LEN + KEYNUM_LEN + SIGNATURE_BYTES);
+    sig.extend_from_slice(&parts.sig_alg);
+    sig.extend_from_slice(&parts.keynum);
+    sig.extend_from_slice(&parts.signature);
+    let s = format!(
+        "untrusted comment: {}\n{}\ntrusted comment: {}\n{}\n",
+        untrusted_comment,
+        Base64::encode_to_string(&sig)?,
+        parts.trusted_comment,
+        Base64::encode_to_string(parts.global_signature)?
+    );
+    SignatureBox::from_string(&s)
+}
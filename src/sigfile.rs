@@ -0,0 +1,296 @@
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+use minisign::*;
+
+use crate::bundle::SignatureBundle;
+use crate::comment::{self, Sanitize, UNTRUSTED_COMMENT_MAX_BYTES};
+use crate::helpers::{create_file, with_suffix};
+use crate::platform;
+use crate::raw::{
+    key_id, parse_key_id, parse_signature_box, signature_box_from_parts, signature_parts,
+};
+
+const UNTRUSTED_COMMENT_PREFIX: &str = "untrusted comment: ";
+const TRUSTED_COMMENT_PREFIX: &str = "trusted comment: ";
+const BOM: char = '\u{feff}';
+
+/// The line without surrounding whitespace, keeping the space after an
+/// empty comment's prefix.
+fn trim_line(line: &str) -> &str {
+    let line = line.trim_start();
+    for prefix in [UNTRUSTED_COMMENT_PREFIX, TRUSTED_COMMENT_PREFIX] {
+        if line.starts_with(prefix) && line[prefix.len()..].trim().is_empty() {
+            return &line[..prefix.len()];
+        }
+    }
+    line.trim_end()
+}
+
+/// A problem found in a signature file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub message: String,
+    /// Whether `cmd_sig_normalize()` fixes it.
+    pub fixable: bool,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)?;
+        if self.fixable {
+            write!(f, " (fixed by normalize)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Find what editors and transfer tools typically do to signature files:
+/// byte order marks, CRLF line endings, blank lines and surrounding
+/// whitespace, as well as malformed signatures.
+pub fn check_signature_file(bytes: &[u8]) -> Vec<Issue> {
+    let mut issues = vec![];
+    let mut issue = |line: usize, message: String, fixable: bool| {
+        issues.push(Issue {
+            line,
+            message,
+            fixable,
+        })
+    };
+    let s = match std::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(err) => {
+            let line = bytes[..err.valid_up_to()]
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count()
+                + 1;
+            issue(line, "invalid UTF-8".to_string(), false);
+            return issues;
+        }
+    };
+    let s = match s.strip_prefix(BOM) {
+        Some(s) => {
+            issue(1, "byte order mark".to_string(), true);
+            s
+        }
+        None => s,
+    };
+    let mut lines: Vec<&str> = s.split('\n').collect();
+    if lines.last() == Some(&"") {
+        lines.pop();
+    } else if !s.is_empty() {
+        issue(
+            lines.len(),
+            "no newline at the end of the file".to_string(),
+            true,
+        );
+    }
+    let mut content = vec![];
+    let mut crlf_lines = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        let number = i + 1;
+        let line = match line.strip_suffix('\r') {
+            Some(line) => {
+                crlf_lines.push(number);
+                line
+            }
+            None => line,
+        };
+        if line.trim().is_empty() {
+            issue(number, "blank line".to_string(), true);
+            continue;
+        }
+        if line.contains('\r') {
+            issue(number, "carriage return inside the line".to_string(), false);
+        }
+        if trim_line(line) != line {
+            issue(number, "leading or trailing whitespace".to_string(), true);
+        }
+        content.push((number, trim_line(line)));
+    }
+    if let Some(&first) = crlf_lines.first() {
+        issue(
+            first,
+            format!("CRLF line endings ({} lines)", crlf_lines.len()),
+            true,
+        );
+    }
+    if content.is_empty() {
+        issue(1, "no signature".to_string(), false);
+    }
+    for chunk in content.chunks(4) {
+        let (first, _) = chunk[0];
+        if chunk.len() != 4 {
+            issue(
+                first,
+                format!(
+                    "truncated signature: 4 lines expected, {} found",
+                    chunk.len()
+                ),
+                false,
+            );
+            continue;
+        }
+        if !chunk[0].1.starts_with(UNTRUSTED_COMMENT_PREFIX) {
+            issue(
+                first,
+                format!("the line should start with `{UNTRUSTED_COMMENT_PREFIX}`"),
+                false,
+            );
+        } else if !chunk[2].1.starts_with(TRUSTED_COMMENT_PREFIX) {
+            issue(
+                chunk[2].0,
+                format!("the line should start with `{TRUSTED_COMMENT_PREFIX}`"),
+                false,
+            );
+        } else {
+            let lines: Vec<&str> = chunk.iter().map(|(_, line)| *line).collect();
+            if let Err(err) = parse_signature_box(&lines.join("\n")) {
+                issue(first, format!("malformed signature: {err}"), false);
+            }
+        }
+    }
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+/// Parse a signature file, ignoring the problems that normalization fixes.
+fn parse_lenient(bytes: &[u8]) -> Result<SignatureBundle> {
+    let s = std::str::from_utf8(bytes)
+        .map_err(|_| PError::new(ErrorKind::Encoding, "the signature file isn't valid UTF-8"))?;
+    let s = s.strip_prefix(BOM).unwrap_or(s);
+    let lines: Vec<&str> = s.lines().map(trim_line).collect();
+    SignatureBundle::from_string(&lines.join("\n"))
+}
+
+/// Replace a signature file atomically, keeping it unchanged on error.
+fn replace_signature_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp_path = with_suffix(path, format!(".tmp{}", std::process::id()));
+    let mut writer = create_file(&tmp_path, 0o644)?;
+    writer.write_all(bytes)?;
+    writer
+        .into_inner()
+        .map_err(|e| PError::new(ErrorKind::Io, e.to_string()))?
+        .sync_all()?;
+    platform::rename(&tmp_path, path).map_err(|e| {
+        let _ = platform::remove_file(&tmp_path);
+        PError::new(
+            ErrorKind::Io,
+            format!("could not update {}: {}", path.display(), e),
+        )
+    })
+}
+
+pub fn cmd_sig_check<P>(signature_path: P) -> Result<Vec<Issue>>
+where
+    P: AsRef<Path>,
+{
+    Ok(check_signature_file(&platform::read(signature_path)?))
+}
+
+/// Rewrite a signature file in canonical form: no byte order mark, LF line
+/// endings, no blank lines or surrounding whitespace. Signatures remain
+/// valid, since whitespace around the trusted comment isn't signed. Returns
+/// whether the file changed.
+pub fn cmd_sig_normalize<P>(signature_path: P) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let signature_path = signature_path.as_ref();
+    let bytes = platform::read(signature_path)?;
+    let normalized = parse_lenient(&bytes)
+        .map_err(|e| {
+            PError::new(
+                ErrorKind::Encoding,
+                format!("{e}, run `rsign sig check` for details"),
+            )
+        })?
+        .to_bytes();
+    if normalized == bytes {
+        return Ok(false);
+    }
+    replace_signature_file(signature_path, &normalized)?;
+    Ok(true)
+}
+
+/// Change the untrusted comment of the signatures made by `key_id`, or of
+/// all of them. The file is normalized as well. Returns the number of
+/// signatures that were changed.
+pub fn cmd_sig_set_untrusted_comment<P>(
+    signature_path: P,
+    untrusted_comment: &str,
+    key_id_filter: Option<&str>,
+) -> Result<usize>
+where
+    P: AsRef<Path>,
+{
+    let signature_path = signature_path.as_ref();
+    let untrusted_comment = comment::sanitize(
+        untrusted_comment,
+        "untrusted comment",
+        UNTRUSTED_COMMENT_MAX_BYTES,
+        Sanitize::Reject,
+    )?;
+    let keynum = key_id_filter.map(parse_key_id).transpose()?;
+    let mut bundle = parse_lenient(&platform::read(signature_path)?)?;
+    let mut changed = 0;
+    for signature_box in bundle.boxes_mut() {
+        if keynum.is_some_and(|keynum| signature_box.keynum() != keynum) {
+            continue;
+        }
+        *signature_box =
+            signature_box_from_parts(&signature_parts(signature_box)?, &untrusted_comment)?;
+        changed += 1;
+    }
+    if changed == 0 {
+        return Err(PError::new(
+            ErrorKind::Misc,
+            format!(
+                "the signature file has no signature by key {}",
+                key_id(&keynum.unwrap_or_default())
+            ),
+        ));
+    }
+    replace_signature_file(signature_path, &bundle.to_bytes())?;
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+
+    #[test]
+    fn test_check_and_normalize() {
+        assert!(check_signature_file(SIGNATURE.as_bytes()).is_empty());
+        let mangled = format!("{BOM}{}\r\n", SIGNATURE.replace('\n', " \r\n"));
+        let issues = check_signature_file(mangled.as_bytes());
+        assert!(issues.iter().all(|issue| issue.fixable), "{:?}", issues);
+        let messages: Vec<_> = issues.iter().map(|issue| issue.message.as_str()).collect();
+        assert_eq!(messages[0], "byte order mark");
+        assert!(messages.contains(&"CRLF line endings (5 lines)"));
+        assert!(messages.contains(&"leading or trailing whitespace"));
+        assert!(messages.contains(&"blank line"));
+        let normalized = parse_lenient(mangled.as_bytes()).unwrap().to_bytes();
+        assert_eq!(normalized, SIGNATURE.as_bytes());
+
+        let truncated: String = SIGNATURE
+            .lines()
+            .take(3)
+            .map(|l| format!("{l}\n"))
+            .collect();
+        let issues = check_signature_file(truncated.as_bytes());
+        assert!(!issues[0].fixable && issues[0].message.starts_with("truncated"));
+        let renamed = SIGNATURE.replace("trusted comment: timestamp", "trusted: timestamp");
+        assert_eq!(check_signature_file(renamed.as_bytes())[0].line, 3);
+    }
+}
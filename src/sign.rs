@@ -0,0 +1,273 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use minisign::*;
+
+use crate::bundle::SignatureBundle;
+use crate::certificate::{Certificate, CERT_FIELD};
+use crate::comment::{self, Sanitize, TRUSTED_COMMENT_MAX_BYTES, UNTRUSTED_COMMENT_MAX_BYTES};
+use crate::expiry::EXPIRES_FIELD;
+use crate::helpers::{
+    create_sig_file, load_secret_key, open_data_file, unix_timestamp, Passphrase,
+};
+use crate::namespace::{
+    check_key_namespace, check_namespace_name, key_namespaces, NAMESPACE_FIELD,
+};
+use crate::platform;
+use crate::prehash::{sign_prehashed, DataSource};
+use crate::raw::key_id;
+use crate::verify::{verify_signature_box, SignedData};
+
+/// Default suffix of countersignature files.
+pub const COUNTERSIG_SUFFIX: &str = ".countersig";
+
+/// Options of `cmd_sign()` and `cmd_countersign()`.
+#[derive(Clone, Default)]
+pub struct SignOptions<'a> {
+    /// Public key used to check the new signature.
+    pub pk: Option<&'a PublicKey>,
+    /// Trusted comment, with a timestamp and the file name by default.
+    pub trusted_comment: Option<&'a str>,
+    pub untrusted_comment: Option<&'a str>,
+    /// Where the password of the secret key comes from.
+    pub passphrase: Passphrase,
+    /// Add the signature to an existing signature file.
+    pub append: bool,
+    /// Certificate of the signing key, embedded in the trusted comment.
+    pub certificate: Option<&'a Certificate>,
+    pub namespace: Option<&'a str>,
+    /// Expiration time, as a UNIX timestamp.
+    pub expires: Option<u64>,
+    /// What to do with control characters in the comments.
+    pub sanitize: Sanitize,
+}
+
+/// A new signature, and where it was saved.
+pub struct SignOutcome {
+    pub key_id: String,
+    pub trusted_comment: String,
+    pub signature_path: PathBuf,
+}
+
+pub fn cmd_sign<P, Q>(
+    sk_path: P,
+    signature_path: Q,
+    data: DataSource<'_>,
+    options: &SignOptions<'_>,
+) -> Result<SignOutcome>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    if let DataSource::CommentOnly = data {
+        return Err(PError::new(
+            ErrorKind::Misc,
+            "a file or a prehash is required to sign",
+        ));
+    }
+    if !platform::exists(&sk_path) {
+        return Err(PError::new(
+            ErrorKind::Io,
+            format!(
+                "can't find secret key file at {}, try using -s",
+                sk_path.as_ref().display()
+            ),
+        ));
+    }
+    let mut bundle = if options.append && platform::exists(&signature_path) {
+        SignatureBundle::from_file(&signature_path).map_err(|err| {
+            PError::new(
+                ErrorKind::Io,
+                format!(
+                    "could not read signature file {}: {}",
+                    signature_path.as_ref().display(),
+                    err
+                ),
+            )
+        })?
+    } else {
+        SignatureBundle::default()
+    };
+    if let Some(namespace) = options.namespace {
+        check_namespace_name(namespace)?;
+    }
+    let allowed_namespaces = key_namespaces(&sk_path)?;
+    let sk = load_secret_key(sk_path, &options.passphrase)?;
+    check_appendable(&bundle, &PublicKey::from_secret_key(&sk)?, &data)?;
+    check_key_namespace(
+        PublicKey::from_secret_key(&sk)?.keynum(),
+        allowed_namespaces.as_deref(),
+        options.namespace,
+    )?;
+    let trusted_comment = match (options.trusted_comment, &data) {
+        (Some(trusted_comment), _) => trusted_comment.to_string(),
+        (None, DataSource::File(data_path)) => format!(
+            "timestamp:{}\tfile:{}\tprehashed",
+            unix_timestamp(),
+            comment::encode_path(data_path)
+        ),
+        (None, _) => format!("timestamp:{}\tprehashed", unix_timestamp()),
+    };
+    let trusted_comment = comment::sanitize(
+        &trusted_comment,
+        "trusted comment",
+        TRUSTED_COMMENT_MAX_BYTES,
+        options.sanitize,
+    )?;
+    let untrusted_comment = options
+        .untrusted_comment
+        .map(|untrusted_comment| {
+            comment::sanitize(
+                untrusted_comment,
+                "untrusted comment",
+                UNTRUSTED_COMMENT_MAX_BYTES,
+                options.sanitize,
+            )
+        })
+        .transpose()?;
+    let trusted_comment = match options.namespace {
+        Some(namespace) => format!("{trusted_comment}\t{NAMESPACE_FIELD}:{namespace}"),
+        None => trusted_comment,
+    };
+    let trusted_comment = match options.expires {
+        Some(expires) => format!("{trusted_comment}\t{EXPIRES_FIELD}:{expires}"),
+        None => trusted_comment,
+    };
+    let trusted_comment = match options.certificate {
+        Some(certificate) => {
+            if certificate.subkey().keynum() != PublicKey::from_secret_key(&sk)?.keynum() {
+                return Err(PError::new(
+                    ErrorKind::Misc,
+                    "the certificate wasn't issued for this secret key",
+                ));
+            }
+            format!(
+                "{}\t{}:{}",
+                trusted_comment,
+                CERT_FIELD,
+                certificate.encode()?
+            )
+        }
+        None => trusted_comment,
+    };
+    comment::check_length(
+        &trusted_comment,
+        "trusted comment",
+        TRUSTED_COMMENT_MAX_BYTES,
+    )?;
+    let signature_box = match data {
+        DataSource::File(data_path) => {
+            let data_reader = open_data_file(data_path)?;
+            sign(
+                options.pk,
+                &sk,
+                data_reader,
+                Some(trusted_comment.as_str()),
+                untrusted_comment.as_deref(),
+            )?
+        }
+        DataSource::Prehash(prehash) => sign_prehashed(
+            options.pk,
+            &sk,
+            prehash,
+            &trusted_comment,
+            untrusted_comment.as_deref(),
+        )?,
+        DataSource::CommentOnly => unreachable!(),
+    };
+    let key_id = key_id(signature_box.keynum());
+    bundle.push(signature_box)?;
+    let mut signature_box_writer = create_sig_file(&signature_path)?;
+    signature_box_writer.write_all(&bundle.to_bytes())?;
+    signature_box_writer.flush()?;
+    Ok(SignOutcome {
+        key_id,
+        trusted_comment,
+        signature_path: signature_path.as_ref().to_path_buf(),
+    })
+}
+
+/// Refuse to add a signature to a bundle whose signatures were made for other
+/// data: every signature must be prehashed like the new one, and the existing
+/// signatures of the signing key itself must be valid for the data.
+fn check_appendable(bundle: &SignatureBundle, pk: &PublicKey, data: &DataSource<'_>) -> Result<()> {
+    if bundle.boxes().is_empty() {
+        return Ok(());
+    }
+    if bundle
+        .boxes()
+        .iter()
+        .any(|signature_box| !signature_box.is_prehashed())
+    {
+        return Err(PError::new(
+            ErrorKind::Misc,
+            "the signature file has legacy signatures, new signatures can't be appended to it",
+        ));
+    }
+    let own_boxes = bundle
+        .boxes()
+        .iter()
+        .filter(|signature_box| signature_box.keynum() == pk.keynum());
+    let mut signed_data = None;
+    for signature_box in own_boxes {
+        let signed_data = match &mut signed_data {
+            Some(signed_data) => signed_data,
+            None => signed_data.insert(SignedData::load(data, false)?),
+        };
+        verify_signature_box(pk, signature_box, signed_data, false).map_err(|_| {
+            PError::new(
+                ErrorKind::Verify,
+                "the signature file has a signature by this key that doesn't match the data, refusing to append to it",
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Countersign a signature file: the exact bytes of the file, including the
+/// trusted comments and their signatures, are signed with another key.
+/// Only the comments, passphrase, `append` and `sanitize` options are used.
+pub fn cmd_countersign<P, Q, R>(
+    sk_path: P,
+    signature_path: Q,
+    countersignature_path: R,
+    options: &SignOptions<'_>,
+) -> Result<SignOutcome>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    let signature_path = signature_path.as_ref();
+    SignatureBundle::from_file(signature_path).map_err(|err| {
+        PError::new(
+            ErrorKind::Io,
+            format!(
+                "could not read signature file {}: {}",
+                signature_path.display(),
+                err
+            ),
+        )
+    })?;
+    let trusted_comment = match options.trusted_comment {
+        Some(trusted_comment) => trusted_comment.to_string(),
+        None => format!(
+            "timestamp:{}\tcountersignature of:{}",
+            unix_timestamp(),
+            comment::encode_path(signature_path)
+        ),
+    };
+    cmd_sign(
+        sk_path,
+        countersignature_path,
+        DataSource::File(signature_path),
+        &SignOptions {
+            trusted_comment: Some(&trusted_comment),
+            untrusted_comment: options.untrusted_comment,
+            passphrase: options.passphrase.clone(),
+            append: options.append,
+            sanitize: options.sanitize,
+            ..Default::default()
+        },
+    )
+}
@@ -0,0 +1,550 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use ct_codecs::{Base64, Decoder, Encoder, Hex};
+use minisign::*;
+use sha2::{Digest, Sha512};
+
+use crate::helpers::{
+    create_file, get_new_password, get_password, load_secret_key, open_data_file,
+    protect_secret_key, unix_timestamp, Passphrase,
+};
+use crate::platform;
+use crate::raw::{
+    key_id, parse_signature_box, secret_key_bytes, secret_key_from_raw, sign_raw,
+    signature_box_from_parts, signature_parts, verify_raw, SignatureParts, PUBLIC_KEY_BYTES,
+    SECRET_KEY_BYTES, SIGNATURE_BYTES,
+};
+
+/// The default suffix for `signify` and `usign` signatures.
+pub const SIGNIFY_SIG_SUFFIX: &str = ".sig";
+
+const COMMENT_PREFIX: &str = "untrusted comment: ";
+const PKALG: [u8; 2] = *b"Ed";
+const KDFALG: [u8; 2] = *b"BK";
+const KDF_ROUNDS: u32 = 42;
+const KEYNUM_BYTES: usize = 8;
+const SALT_BYTES: usize = 16;
+const CHECKSUM_BYTES: usize = 8;
+const PK_BYTES: usize = 2 + KEYNUM_BYTES + PUBLIC_KEY_BYTES;
+const SK_BYTES: usize = 2 + 2 + 4 + SALT_BYTES + CHECKSUM_BYTES + KEYNUM_BYTES + SECRET_KEY_BYTES;
+const SIG_BYTES: usize = 2 + KEYNUM_BYTES + SIGNATURE_BYTES;
+const MINISIGN_SK_BYTES: usize = 158;
+
+/// Key and signature formats that `rsign convert` can produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Minisign,
+    Signify,
+    Usign,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Format> {
+        match name {
+            "minisign" => Ok(Format::Minisign),
+            "signify" => Ok(Format::Signify),
+            "usign" => Ok(Format::Usign),
+            _ => Err(PError::new(
+                ErrorKind::Misc,
+                format!("unsupported format: {name}"),
+            )),
+        }
+    }
+}
+
+/// A `signify`/`usign` secret key, possibly encrypted with bcrypt_pbkdf.
+pub struct SignifySecretKey {
+    kdf_rounds: u32,
+    salt: [u8; SALT_BYTES],
+    checksum: [u8; CHECKSUM_BYTES],
+    keynum: [u8; KEYNUM_BYTES],
+    sk: [u8; SECRET_KEY_BYTES],
+}
+
+/// A `signify`/`usign` signature over the raw content of a file.
+pub struct SignifySignature {
+    keynum: [u8; KEYNUM_BYTES],
+    sig: [u8; SIGNATURE_BYTES],
+}
+
+enum Document {
+    PublicKey(PublicKey),
+    MinisignSecretKey,
+    SignifySecretKey(SignifySecretKey),
+    MinisignSignature(SignatureBox),
+    SignifySignature(SignifySignature),
+}
+
+fn decode_box(s: &str) -> Result<Vec<u8>> {
+    let mut lines = s.lines();
+    let comment = lines
+        .next()
+        .ok_or_else(|| PError::new(ErrorKind::Encoding, "Missing untrusted comment"))?;
+    if !comment.starts_with(COMMENT_PREFIX) {
+        return Err(PError::new(
+            ErrorKind::Encoding,
+            format!("Untrusted comment must start with: {COMMENT_PREFIX}"),
+        ));
+    }
+    let encoded = lines
+        .next()
+        .ok_or_else(|| PError::new(ErrorKind::Encoding, "Missing base64-encoded data"))?;
+    Base64::decode_to_vec(encoded.trim(), None).map_err(|e| {
+        PError::new(
+            ErrorKind::Encoding,
+            format!("Base64 conversion failed: {e}"),
+        )
+    })
+}
+
+fn encode_box(comment: &str, bytes: &[u8]) -> Result<String> {
+    Ok(format!(
+        "{}{}\n{}\n",
+        COMMENT_PREFIX,
+        comment,
+        Base64::encode_to_string(bytes)?
+    ))
+}
+
+fn check_pkalg(bytes: &[u8]) -> Result<()> {
+    if bytes[..2] != PKALG {
+        return Err(PError::new(
+            ErrorKind::Encoding,
+            "Unsupported signature algorithm",
+        ));
+    }
+    Ok(())
+}
+
+fn usign_fingerprint(keynum: &[u8]) -> Result<String> {
+    Ok(Hex::encode_to_string(keynum)?)
+}
+
+impl SignifySecretKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<SignifySecretKey> {
+        if bytes.len() != SK_BYTES {
+            return Err(PError::new(
+                ErrorKind::Encoding,
+                "Invalid secret key length",
+            ));
+        }
+        check_pkalg(bytes)?;
+        if bytes[2..4] != KDFALG {
+            return Err(PError::new(
+                ErrorKind::Encoding,
+                "Unsupported key derivation algorithm",
+            ));
+        }
+        let mut rounds = [0u8; 4];
+        rounds.copy_from_slice(&bytes[4..8]);
+        let mut sk = SignifySecretKey {
+            kdf_rounds: u32::from_be_bytes(rounds),
+            salt: [0u8; SALT_BYTES],
+            checksum: [0u8; CHECKSUM_BYTES],
+            keynum: [0u8; KEYNUM_BYTES],
+            sk: [0u8; SECRET_KEY_BYTES],
+        };
+        let mut offset = 8;
+        sk.salt.copy_from_slice(&bytes[offset..offset + SALT_BYTES]);
+        offset += SALT_BYTES;
+        sk.checksum
+            .copy_from_slice(&bytes[offset..offset + CHECKSUM_BYTES]);
+        offset += CHECKSUM_BYTES;
+        sk.keynum
+            .copy_from_slice(&bytes[offset..offset + KEYNUM_BYTES]);
+        offset += KEYNUM_BYTES;
+        sk.sk.copy_from_slice(&bytes[offset..]);
+        Ok(sk)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SK_BYTES);
+        bytes.extend_from_slice(&PKALG);
+        bytes.extend_from_slice(&KDFALG);
+        bytes.extend_from_slice(&self.kdf_rounds.to_be_bytes());
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.checksum);
+        bytes.extend_from_slice(&self.keynum);
+        bytes.extend_from_slice(&self.sk);
+        bytes
+    }
+
+    fn xor_key(&mut self, passphrase: &str) -> Result<()> {
+        if self.kdf_rounds == 0 {
+            return Ok(());
+        }
+        let mut xorkey = [0u8; SECRET_KEY_BYTES];
+        bcrypt_pbkdf::bcrypt_pbkdf(passphrase, &self.salt, self.kdf_rounds, &mut xorkey)
+            .map_err(|e| PError::new(ErrorKind::KDF, e.to_string()))?;
+        for (byte, xor) in self.sk.iter_mut().zip(xorkey.iter()) {
+            *byte ^= *xor;
+        }
+        Ok(())
+    }
+
+    /// Wrap a raw Ed25519 secret key, encrypting it if a passphrase is given.
+    pub fn new(
+        keynum: &[u8; KEYNUM_BYTES],
+        raw: &[u8; SECRET_KEY_BYTES],
+        passphrase: Option<&str>,
+    ) -> Result<SignifySecretKey> {
+        let mut checksum = [0u8; CHECKSUM_BYTES];
+        checksum.copy_from_slice(&Sha512::digest(raw)[..CHECKSUM_BYTES]);
+        let mut sk = SignifySecretKey {
+            kdf_rounds: 0,
+            salt: [0u8; SALT_BYTES],
+            checksum,
+            keynum: *keynum,
+            sk: *raw,
+        };
+        if let Some(passphrase) = passphrase {
+            sk.kdf_rounds = KDF_ROUNDS;
+            getrandom::fill(&mut sk.salt)?;
+            sk.xor_key(passphrase)?;
+        }
+        Ok(sk)
+    }
+
+    /// Decrypt the key if needed, and return the raw Ed25519 secret key.
+    pub fn into_raw(
+        mut self,
+        passphrase: &Passphrase,
+    ) -> Result<([u8; KEYNUM_BYTES], [u8; SECRET_KEY_BYTES])> {
+        if self.kdf_rounds > 0 {
+            let passphrase = match passphrase.password()? {
+                Some(passphrase) => passphrase,
+                None => get_password("passphrase: ")?,
+            };
+            self.xor_key(&passphrase)?;
+        }
+        if Sha512::digest(self.sk)[..CHECKSUM_BYTES] != self.checksum {
+            return Err(PError::new(ErrorKind::Verify, "incorrect passphrase"));
+        }
+        Ok((self.keynum, self.sk))
+    }
+}
+
+impl SignifySignature {
+    pub fn from_bytes(bytes: &[u8]) -> Result<SignifySignature> {
+        if bytes.len() != SIG_BYTES {
+            return Err(PError::new(ErrorKind::Encoding, "Invalid signature length"));
+        }
+        check_pkalg(bytes)?;
+        let mut sig = SignifySignature {
+            keynum: [0u8; KEYNUM_BYTES],
+            sig: [0u8; SIGNATURE_BYTES],
+        };
+        sig.keynum.copy_from_slice(&bytes[2..2 + KEYNUM_BYTES]);
+        sig.sig.copy_from_slice(&bytes[2 + KEYNUM_BYTES..]);
+        Ok(sig)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SIG_BYTES);
+        bytes.extend_from_slice(&PKALG);
+        bytes.extend_from_slice(&self.keynum);
+        bytes.extend_from_slice(&self.sig);
+        bytes
+    }
+
+    pub fn from_file<P>(sig_path: P) -> Result<SignifySignature>
+    where
+        P: AsRef<Path>,
+    {
+        let s = platform::read_to_string(sig_path)?;
+        SignifySignature::from_bytes(&decode_box(&s)?)
+    }
+}
+
+fn parse_document(s: &str) -> Result<Document> {
+    if s.lines().filter(|line| !line.trim().is_empty()).count() >= 4 {
+        return Ok(Document::MinisignSignature(parse_signature_box(s)?));
+    }
+    let bytes = decode_box(s)?;
+    match bytes.len() {
+        PK_BYTES => {
+            check_pkalg(&bytes)?;
+            Ok(Document::PublicKey(PublicKey::from_bytes(&bytes)?))
+        }
+        SK_BYTES => Ok(Document::SignifySecretKey(SignifySecretKey::from_bytes(
+            &bytes,
+        )?)),
+        SIG_BYTES => Ok(Document::SignifySignature(SignifySignature::from_bytes(
+            &bytes,
+        )?)),
+        MINISIGN_SK_BYTES => Ok(Document::MinisignSecretKey),
+        _ => Err(PError::new(
+            ErrorKind::Encoding,
+            "unrecognized key or signature format",
+        )),
+    }
+}
+
+fn convert_public_key(pk: &PublicKey, to: Format) -> Result<String> {
+    match to {
+        Format::Minisign => Ok(pk.to_box()?.into_string()),
+        Format::Signify => encode_box("signify public key", &pk.to_bytes()),
+        Format::Usign => encode_box(
+            &format!("public key {}", usign_fingerprint(pk.keynum())?),
+            &pk.to_bytes(),
+        ),
+    }
+}
+
+fn signify_secret_key_string(
+    sk: &SignifySecretKey,
+    to: Format,
+    comment: Option<&str>,
+) -> Result<String> {
+    let default_comment = match to {
+        Format::Usign => format!("private key {}", usign_fingerprint(&sk.keynum)?),
+        _ => "signify secret key".to_string(),
+    };
+    encode_box(comment.unwrap_or(&default_comment), &sk.to_bytes())
+}
+
+fn signify_signature_string(sig: &SignifySignature, to: Format) -> Result<String> {
+    let comment = match to {
+        Format::Usign => format!("signed by key {}", usign_fingerprint(&sig.keynum)?),
+        _ => "verify with signify public key".to_string(),
+    };
+    encode_box(&comment, &sig.to_bytes())
+}
+
+/// Translate a public key, secret key or signature between the `minisign`
+/// and `signify`/`usign` formats.
+#[allow(clippy::too_many_arguments)]
+pub fn cmd_convert<P, Q>(
+    input_path: P,
+    output_path: Q,
+    to: Format,
+    sk_path: Option<&Path>,
+    comment: Option<&str>,
+    passphrase: &Passphrase,
+    passwordless: bool,
+    unencrypted: bool,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let input = platform::read_to_string(&input_path).map_err(|e| {
+        PError::new(
+            ErrorKind::Io,
+            format!("could not read {}: {}", input_path.as_ref().display(), e),
+        )
+    })?;
+    let (output, mode) = match (parse_document(&input)?, to) {
+        (Document::PublicKey(pk), to) => (convert_public_key(&pk, to)?, 0o644),
+        (Document::MinisignSecretKey, Format::Minisign) => {
+            return Err(PError::new(
+                ErrorKind::Misc,
+                "the secret key is already in minisign format",
+            ))
+        }
+        (Document::MinisignSecretKey, to) => {
+            let sk = load_secret_key(&input_path, passphrase)?;
+            let mut keynum = [0u8; KEYNUM_BYTES];
+            keynum.copy_from_slice(sk.keynum());
+            let passphrase = if passwordless || unencrypted {
+                None
+            } else {
+                Some(get_new_password()?)
+            };
+            let sk = SignifySecretKey::new(&keynum, &secret_key_bytes(&sk), passphrase.as_deref())?;
+            (signify_secret_key_string(&sk, to, comment)?, 0o600)
+        }
+        (Document::SignifySecretKey(sk), Format::Minisign) => {
+            let (keynum, raw) = sk.into_raw(passphrase)?;
+            let sk = secret_key_from_raw(&keynum, &raw)?;
+            let sk = protect_secret_key(&sk, passwordless, unencrypted)?;
+            (sk.to_box(comment)?.into_string(), 0o600)
+        }
+        (Document::SignifySecretKey(sk), to) => {
+            (signify_secret_key_string(&sk, to, comment)?, 0o600)
+        }
+        (Document::MinisignSignature(_), Format::Minisign) => {
+            return Err(PError::new(
+                ErrorKind::Misc,
+                "the signature is already in minisign format",
+            ))
+        }
+        (Document::MinisignSignature(signature_box), to) => {
+            if signature_box.is_prehashed() {
+                return Err(PError::new(
+                    ErrorKind::Misc,
+                    "prehashed signatures can't be converted: signify and usign only \
+                     verify signatures over the raw file content",
+                ));
+            }
+            let parts = signature_parts(&signature_box)?;
+            let sig = SignifySignature {
+                keynum: parts.keynum,
+                sig: parts.signature,
+            };
+            (signify_signature_string(&sig, to)?, 0o644)
+        }
+        (Document::SignifySignature(sig), Format::Minisign) => {
+            let sk_path = sk_path.ok_or_else(|| {
+                PError::new(
+                    ErrorKind::Misc,
+                    "a minisign signature includes a signed trusted comment: \
+                     the secret key is required to convert it (use -s)",
+                )
+            })?;
+            let sk = load_secret_key(sk_path, passphrase)?;
+            if sk.keynum() != sig.keynum {
+                return Err(PError::new(
+                    ErrorKind::Verify,
+                    format!(
+                        "Signature key id: {} is different from secret key: {}",
+                        key_id(&sig.keynum),
+                        key_id(sk.keynum())
+                    ),
+                ));
+            }
+            let trusted_comment = format!("timestamp:{}\tconverted from signify", unix_timestamp());
+            let mut sig_and_trusted_comment = sig.sig.to_vec();
+            sig_and_trusted_comment.extend_from_slice(trusted_comment.as_bytes());
+            let parts = SignatureParts {
+                sig_alg: PKALG,
+                keynum: sig.keynum,
+                signature: sig.sig,
+                global_signature: sign_raw(&sk, &sig_and_trusted_comment)?,
+                trusted_comment,
+            };
+            let signature_box =
+                signature_box_from_parts(&parts, comment.unwrap_or(DEFAULT_COMMENT))?;
+            (signature_box.into_string(), 0o644)
+        }
+        (Document::SignifySignature(sig), to) => (signify_signature_string(&sig, to)?, 0o644),
+    };
+    let mut writer = create_file(&output_path, mode)?;
+    writer.write_all(output.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Verify a `signify`/`usign` signature over the raw content of a file,
+/// and return that content.
+pub fn cmd_verify_signify<P, Q>(pk: PublicKey, data_path: P, signature_path: Q) -> Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let sig = SignifySignature::from_file(&signature_path).map_err(|err| {
+        PError::new(
+            ErrorKind::Io,
+            format!(
+                "could not read signature file {}: {}",
+                signature_path.as_ref().display(),
+                err
+            ),
+        )
+    })?;
+    let mut data = vec![];
+    open_data_file(&data_path)
+        .and_then(|mut reader| Ok(reader.read_to_end(&mut data)?))
+        .map_err(|err| {
+            PError::new(
+                ErrorKind::Io,
+                format!(
+                    "could not read data file {}: {}",
+                    data_path.as_ref().display(),
+                    err
+                ),
+            )
+        })?;
+    if sig.keynum != pk.keynum() {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            format!(
+                "Signature key id: {} is different from public key: {}",
+                key_id(&sig.keynum),
+                key_id(pk.keynum())
+            ),
+        ));
+    }
+    if !verify_raw(&pk, &data, &sig.sig) {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            "Signature verification failed",
+        ));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{with_platform, MemoryPlatform};
+
+    #[test]
+    fn test_secret_key_round_trip() {
+        let KeyPair { sk, .. } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let mut keynum = [0u8; KEYNUM_BYTES];
+        keynum.copy_from_slice(sk.keynum());
+        let raw = secret_key_bytes(&sk);
+        let signify_sk = SignifySecretKey::new(&keynum, &raw, None).unwrap();
+        let signify_sk = SignifySecretKey::from_bytes(&signify_sk.to_bytes()).unwrap();
+        let (keynum2, raw2) = signify_sk.into_raw(&Passphrase::Prompt).unwrap();
+        assert_eq!(keynum, keynum2);
+        assert_eq!(raw, raw2);
+        let sk2 = secret_key_from_raw(&keynum2, &raw2).unwrap();
+        assert_eq!(sk, sk2);
+    }
+
+    #[test]
+    fn test_encrypted_secret_key() {
+        let raw = [7u8; SECRET_KEY_BYTES];
+        let mut sk = SignifySecretKey::new(&[1u8; KEYNUM_BYTES], &raw, Some("pass")).unwrap();
+        assert_eq!(sk.kdf_rounds, KDF_ROUNDS);
+        assert_ne!(sk.sk, raw);
+        let encrypted = SignifySecretKey::from_bytes(&sk.to_bytes()).unwrap();
+        sk.xor_key("pass").unwrap();
+        assert_eq!(sk.sk, raw);
+
+        let platform = MemoryPlatform::new(0).with_env("SIGNIFY_PASS", "pass");
+        let (_, raw2) = with_platform(platform, || {
+            encrypted.into_raw(&Passphrase::Env("SIGNIFY_PASS".to_string()))
+        })
+        .unwrap();
+        assert_eq!(raw2, raw);
+    }
+
+    #[test]
+    fn test_parse_documents() {
+        let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let signify_pk = convert_public_key(&pk, Format::Signify).unwrap();
+        assert!(matches!(
+            parse_document(&signify_pk).unwrap(),
+            Document::PublicKey(ref pk2) if *pk2 == pk
+        ));
+        let sk_box = sk.to_box(None).unwrap().into_string();
+        assert!(matches!(
+            parse_document(&sk_box).unwrap(),
+            Document::MinisignSecretKey
+        ));
+        let sig = SignifySignature {
+            keynum: [2u8; KEYNUM_BYTES],
+            sig: [3u8; SIGNATURE_BYTES],
+        };
+        let sig_str = signify_signature_string(&sig, Format::Usign).unwrap();
+        assert!(sig_str.starts_with("untrusted comment: signed by key 0202020202020202\n"));
+        assert!(matches!(
+            parse_document(&sig_str).unwrap(),
+            Document::SignifySignature(_)
+        ));
+        assert!(parse_document("untrusted comment: x\nAAAA\n").is_err());
+    }
+
+    #[test]
+    fn test_verify_signify_signature() {
+        let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let data = b"signed with signify";
+        let sig = sign_raw(&sk, data).unwrap();
+        assert!(verify_raw(&pk, data, &sig));
+        assert!(!verify_raw(&pk, b"other data", &sig));
+    }
+}
@@ -0,0 +1,147 @@
+use ct_codecs::{Base64, Base64NoPadding, Decoder, Encoder};
+use minisign::*;
+use sha2::{Digest, Sha256};
+
+use crate::raw::PUBLIC_KEY_BYTES;
+
+/// The OpenSSH name of Ed25519 keys and signatures.
+pub const SSH_ED25519: &str = "ssh-ed25519";
+
+pub fn put_u32(buf: &mut Vec<u8>, x: u32) {
+    buf.extend_from_slice(&x.to_be_bytes());
+}
+
+pub fn put_string(buf: &mut Vec<u8>, s: &[u8]) {
+    put_u32(buf, s.len() as u32);
+    buf.extend_from_slice(s);
+}
+
+/// A reader for the SSH wire encoding (RFC 4251).
+pub struct SshReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SshReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        SshReader { data, pos: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            return Err(PError::new(ErrorKind::Encoding, "truncated SSH data"));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        let mut x = [0u8; 4];
+        x.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_be_bytes(x))
+    }
+
+    pub fn read_string(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        self.read_bytes(len)
+    }
+
+    pub fn read_str(&mut self) -> Result<&'a str> {
+        std::str::from_utf8(self.read_string()?)
+            .map_err(|_| PError::new(ErrorKind::Encoding, "invalid UTF-8 in SSH string"))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+pub fn public_key_blob(pk: &[u8; PUBLIC_KEY_BYTES]) -> Vec<u8> {
+    let mut blob = vec![];
+    put_string(&mut blob, SSH_ED25519.as_bytes());
+    put_string(&mut blob, pk);
+    blob
+}
+
+pub fn parse_public_key_blob(blob: &[u8]) -> Result<[u8; PUBLIC_KEY_BYTES]> {
+    let mut reader = SshReader::new(blob);
+    let key_type = reader.read_str()?;
+    if key_type != SSH_ED25519 {
+        return Err(PError::new(
+            ErrorKind::Encoding,
+            format!("unsupported SSH key type: {key_type}"),
+        ));
+    }
+    let raw = reader.read_string()?;
+    if raw.len() != PUBLIC_KEY_BYTES || !reader.is_empty() {
+        return Err(PError::new(
+            ErrorKind::Encoding,
+            "invalid Ed25519 SSH public key",
+        ));
+    }
+    let mut pk = [0u8; PUBLIC_KEY_BYTES];
+    pk.copy_from_slice(raw);
+    Ok(pk)
+}
+
+/// Encode a public key the way it appears in `authorized_keys` files.
+pub fn openssh_public_key(pk: &[u8; PUBLIC_KEY_BYTES], comment: &str) -> Result<String> {
+    let encoded = Base64::encode_to_string(public_key_blob(pk))?;
+    if comment.is_empty() {
+        Ok(format!("{SSH_ED25519} {encoded}"))
+    } else {
+        Ok(format!("{SSH_ED25519} {encoded} {comment}"))
+    }
+}
+
+/// Parse the `<type> <base64>` part of an OpenSSH public key line.
+pub fn parse_openssh_public_key(key_type: &str, encoded: &str) -> Result<[u8; PUBLIC_KEY_BYTES]> {
+    if key_type != SSH_ED25519 {
+        return Err(PError::new(
+            ErrorKind::Encoding,
+            format!("unsupported SSH key type: {key_type}"),
+        ));
+    }
+    let blob = Base64::decode_to_vec(encoded, None).map_err(|e| {
+        PError::new(
+            ErrorKind::Encoding,
+            format!("Base64 conversion failed: {e}"),
+        )
+    })?;
+    parse_public_key_blob(&blob)
+}
+
+/// The `SHA256:` fingerprint shown by OpenSSH tools.
+pub fn fingerprint(pk: &[u8; PUBLIC_KEY_BYTES]) -> Result<String> {
+    let digest = Sha256::digest(public_key_blob(pk));
+    Ok(format!(
+        "SHA256:{}",
+        Base64NoPadding::encode_to_string(digest)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openssh_public_key_round_trip() {
+        let pk = [42u8; PUBLIC_KEY_BYTES];
+        let line = openssh_public_key(&pk, "comment").unwrap();
+        let mut fields = line.split_whitespace();
+        let key_type = fields.next().unwrap();
+        let encoded = fields.next().unwrap();
+        assert_eq!(fields.next(), Some("comment"));
+        assert_eq!(parse_openssh_public_key(key_type, encoded).unwrap(), pk);
+        assert!(parse_openssh_public_key("ssh-rsa", encoded).is_err());
+    }
+
+    #[test]
+    fn test_reader_rejects_truncated_data() {
+        let mut buf = vec![];
+        put_u32(&mut buf, 10);
+        buf.extend_from_slice(b"short");
+        assert!(SshReader::new(&buf).read_string().is_err());
+    }
+}
@@ -0,0 +1,717 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use ct_codecs::{Base64, Decoder, Encoder};
+use minisign::*;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::helpers::{
+    create_sig_file, load_secret_key, open_data_file, unix_timestamp, utc_timestamp, with_suffix,
+    Passphrase,
+};
+use crate::platform;
+use crate::raw::{
+    public_key_bytes, public_key_from_raw, secret_key_bytes, sign_raw, verify_raw,
+    PUBLIC_KEY_BYTES, SIGNATURE_BYTES,
+};
+use crate::ssh::{
+    fingerprint, openssh_public_key, parse_openssh_public_key, parse_public_key_blob,
+    public_key_blob, put_string, put_u32, SshReader, SSH_ED25519,
+};
+
+const MAGIC: &[u8] = b"SSHSIG";
+const VERSION: u32 = 1;
+const ARMOR_BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+const ARMOR_END: &str = "-----END SSH SIGNATURE-----";
+const ARMOR_LINE_LEN: usize = 70;
+const DEFAULT_HASH_ALG: &str = "sha512";
+
+/// The `-Y` operations of `ssh-keygen` that rsign implements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SshKeygenOp {
+    Sign,
+    Verify,
+    FindPrincipals,
+    CheckNovalidate,
+}
+
+impl SshKeygenOp {
+    pub fn from_name(name: &str) -> Result<SshKeygenOp> {
+        match name {
+            "sign" => Ok(SshKeygenOp::Sign),
+            "verify" => Ok(SshKeygenOp::Verify),
+            "find-principals" => Ok(SshKeygenOp::FindPrincipals),
+            "check-novalidate" => Ok(SshKeygenOp::CheckNovalidate),
+            _ => Err(PError::new(
+                ErrorKind::Misc,
+                format!("unsupported operation: {name}"),
+            )),
+        }
+    }
+}
+
+/// Options given with `-O`.
+#[derive(Default)]
+pub struct SshKeygenOptions {
+    hash_alg: Option<String>,
+    verify_time: Option<u64>,
+}
+
+impl SshKeygenOptions {
+    pub fn parse(options: &[String]) -> Result<SshKeygenOptions> {
+        let mut parsed = SshKeygenOptions::default();
+        for option in options {
+            if let Some(hash_alg) = option.strip_prefix("hashalg=") {
+                check_hash_alg(hash_alg)?;
+                parsed.hash_alg = Some(hash_alg.to_string());
+            } else if let Some(verify_time) = option.strip_prefix("verify-time=") {
+                parsed.verify_time = Some(parse_ssh_time(verify_time)?);
+            } else {
+                return Err(PError::new(
+                    ErrorKind::Misc,
+                    format!("unsupported option: {option}"),
+                ));
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// An SSHSIG signature, as produced by `ssh-keygen -Y sign`.
+pub struct SshSignature {
+    pk: [u8; PUBLIC_KEY_BYTES],
+    namespace: String,
+    hash_alg: String,
+    signature: [u8; SIGNATURE_BYTES],
+}
+
+fn check_hash_alg(hash_alg: &str) -> Result<()> {
+    match hash_alg {
+        "sha256" | "sha512" => Ok(()),
+        _ => Err(PError::new(
+            ErrorKind::Misc,
+            format!("unsupported hash algorithm: {hash_alg}"),
+        )),
+    }
+}
+
+fn digest_reader<D, R>(mut reader: R) -> Result<Vec<u8>>
+where
+    D: Digest,
+    R: Read,
+{
+    let mut state = D::new();
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        state.update(&buf[..len]);
+    }
+    Ok(state.finalize().to_vec())
+}
+
+fn hash_message<R>(reader: R, hash_alg: &str) -> Result<Vec<u8>>
+where
+    R: Read,
+{
+    match hash_alg {
+        "sha256" => digest_reader::<Sha256, _>(reader),
+        "sha512" => digest_reader::<Sha512, _>(reader),
+        _ => Err(PError::new(
+            ErrorKind::Misc,
+            format!("unsupported hash algorithm: {hash_alg}"),
+        )),
+    }
+}
+
+fn signed_data(namespace: &str, hash_alg: &str, hash: &[u8]) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    put_string(&mut data, namespace.as_bytes());
+    put_string(&mut data, b"");
+    put_string(&mut data, hash_alg.as_bytes());
+    put_string(&mut data, hash);
+    data
+}
+
+impl SshSignature {
+    pub fn sign<R>(sk: &SecretKey, namespace: &str, hash_alg: &str, reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        if namespace.is_empty() {
+            return Err(PError::new(ErrorKind::Sign, "a namespace is required"));
+        }
+        let hash = hash_message(reader, hash_alg)?;
+        let signature = sign_raw(sk, &signed_data(namespace, hash_alg, &hash))?;
+        let mut pk = [0u8; PUBLIC_KEY_BYTES];
+        pk.copy_from_slice(&secret_key_bytes(sk)[PUBLIC_KEY_BYTES..]);
+        Ok(SshSignature {
+            pk,
+            namespace: namespace.to_string(),
+            hash_alg: hash_alg.to_string(),
+            signature,
+        })
+    }
+
+    pub fn verify<R>(&self, namespace: &str, reader: R) -> Result<()>
+    where
+        R: Read,
+    {
+        if self.namespace != namespace {
+            return Err(PError::new(
+                ErrorKind::Verify,
+                format!(
+                    "Signature namespace \"{}\" does not match the expected \"{}\"",
+                    self.namespace, namespace
+                ),
+            ));
+        }
+        let hash = hash_message(reader, &self.hash_alg)?;
+        let pk = public_key_from_raw(&[0u8; 8], &self.pk)?;
+        if !verify_raw(
+            &pk,
+            &signed_data(&self.namespace, &self.hash_alg, &hash),
+            &self.signature,
+        ) {
+            return Err(PError::new(
+                ErrorKind::Verify,
+                "Signature verification failed",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn to_armored(&self) -> Result<String> {
+        let mut sig_blob = vec![];
+        put_string(&mut sig_blob, SSH_ED25519.as_bytes());
+        put_string(&mut sig_blob, &self.signature);
+        let mut blob = MAGIC.to_vec();
+        put_u32(&mut blob, VERSION);
+        put_string(&mut blob, &public_key_blob(&self.pk));
+        put_string(&mut blob, self.namespace.as_bytes());
+        put_string(&mut blob, b"");
+        put_string(&mut blob, self.hash_alg.as_bytes());
+        put_string(&mut blob, &sig_blob);
+        let encoded = Base64::encode_to_string(blob)?;
+        let mut armored = format!("{ARMOR_BEGIN}\n");
+        for line in encoded.as_bytes().chunks(ARMOR_LINE_LEN) {
+            armored.push_str(std::str::from_utf8(line).unwrap());
+            armored.push('\n');
+        }
+        armored.push_str(ARMOR_END);
+        armored.push('\n');
+        Ok(armored)
+    }
+
+    pub fn from_armored(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let encoded = s
+            .strip_prefix(ARMOR_BEGIN)
+            .and_then(|s| s.strip_suffix(ARMOR_END))
+            .ok_or_else(|| PError::new(ErrorKind::Encoding, "Missing SSH signature armor"))?;
+        let encoded: String = encoded.split_whitespace().collect();
+        let blob = Base64::decode_to_vec(&encoded, None)?;
+        let mut reader = SshReader::new(&blob);
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(PError::new(
+                ErrorKind::Encoding,
+                "Invalid SSH signature magic",
+            ));
+        }
+        let version = reader.read_u32()?;
+        if version != VERSION {
+            return Err(PError::new(
+                ErrorKind::Encoding,
+                format!("Unsupported SSH signature version: {version}"),
+            ));
+        }
+        let pk = parse_public_key_blob(reader.read_string()?)?;
+        let namespace = reader.read_str()?.to_string();
+        reader.read_string()?;
+        let hash_alg = reader.read_str()?.to_string();
+        check_hash_alg(&hash_alg)?;
+        let mut sig_reader = SshReader::new(reader.read_string()?);
+        if !reader.is_empty() {
+            return Err(PError::new(
+                ErrorKind::Encoding,
+                "Trailing data in SSH signature",
+            ));
+        }
+        if sig_reader.read_str()? != SSH_ED25519 {
+            return Err(PError::new(
+                ErrorKind::Encoding,
+                "Unsupported SSH signature algorithm",
+            ));
+        }
+        let raw_signature = sig_reader.read_string()?;
+        if raw_signature.len() != SIGNATURE_BYTES || !sig_reader.is_empty() {
+            return Err(PError::new(
+                ErrorKind::Encoding,
+                "Invalid Ed25519 signature",
+            ));
+        }
+        let mut signature = [0u8; SIGNATURE_BYTES];
+        signature.copy_from_slice(raw_signature);
+        Ok(SshSignature {
+            pk,
+            namespace,
+            hash_alg,
+            signature,
+        })
+    }
+
+    pub fn from_file<P>(sig_path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let s = platform::read_to_string(&sig_path).map_err(|err| {
+            PError::new(
+                ErrorKind::Io,
+                format!(
+                    "could not read signature file {}: {}",
+                    sig_path.as_ref().display(),
+                    err
+                ),
+            )
+        })?;
+        SshSignature::from_armored(&s)
+    }
+}
+
+/// Parse a time in the `YYYYMMDD[HHMM[SS]][Z]` format used by OpenSSH.
+/// Like OpenSSH, times are in the local time zone, or in UTC with `Z`.
+pub fn parse_ssh_time(s: &str) -> Result<u64> {
+    let invalid = || PError::new(ErrorKind::Misc, format!("invalid time: {s}"));
+    let (digits, utc) = match s.strip_suffix(['Z', 'z']) {
+        Some(digits) => (digits, true),
+        None => (s, false),
+    };
+    if !digits.bytes().all(|c| c.is_ascii_digit()) || ![8, 12, 14].contains(&digits.len()) {
+        return Err(invalid());
+    }
+    let field = |range: std::ops::Range<usize>| -> u64 {
+        digits.get(range).map_or(0, |x| x.parse().unwrap_or(0))
+    };
+    let timestamp = utc_timestamp(
+        field(0..4),
+        field(4..6),
+        field(6..8),
+        field(8..10),
+        field(10..12),
+        field(12..14),
+    )
+    .ok_or_else(invalid)?;
+    if utc {
+        return Ok(timestamp);
+    }
+    platform::current()
+        .local_to_utc(timestamp)
+        .ok_or_else(invalid)
+}
+
+/// Match a string against an OpenSSH pattern using `*` and `?` wildcards.
+fn match_pattern(pattern: &[u8], s: &[u8]) -> bool {
+    // Only backtrack to the last `*`, so that patterns with many wildcards
+    // can't take exponential time
+    let (mut p, mut i) = (0, 0);
+    let mut last_star = None;
+    while i < s.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                last_star = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match last_star {
+                Some((star_p, star_i)) => {
+                    last_star = Some((star_p, star_i + 1));
+                    p = star_p + 1;
+                    i = star_i + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match a string against a comma-separated list of patterns, where a
+/// negated pattern (`!pattern`) that matches rejects the string.
+pub fn match_pattern_list(list: &str, s: &str) -> bool {
+    let mut matched = false;
+    for pattern in list.split(',') {
+        match pattern.strip_prefix('!') {
+            Some(negated) if match_pattern(negated.as_bytes(), s.as_bytes()) => return false,
+            Some(_) => {}
+            None => matched |= match_pattern(pattern.as_bytes(), s.as_bytes()),
+        }
+    }
+    matched
+}
+
+/// An entry of an `allowed_signers` file (see `ssh-keygen(1)`).
+pub struct AllowedSigner {
+    principals: String,
+    cert_authority: bool,
+    namespaces: Option<String>,
+    valid_after: Option<u64>,
+    valid_before: Option<u64>,
+    pk: Option<[u8; PUBLIC_KEY_BYTES]>,
+}
+
+fn next_token(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return None;
+    }
+    let mut in_quotes = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => return Some((&s[..i], &s[i..])),
+            _ => {}
+        }
+    }
+    Some((s, ""))
+}
+
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+fn is_key_type(s: &str) -> bool {
+    s.starts_with("ssh-") || s.starts_with("ecdsa-") || s.starts_with("sk-")
+}
+
+impl AllowedSigner {
+    fn parse_line(line: &str) -> Result<Option<AllowedSigner>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let invalid = || PError::new(ErrorKind::Encoding, format!("invalid line: {line}"));
+        let (principals, rest) = next_token(line).ok_or_else(invalid)?;
+        let mut signer = AllowedSigner {
+            principals: unquote(principals).to_string(),
+            cert_authority: false,
+            namespaces: None,
+            valid_after: None,
+            valid_before: None,
+            pk: None,
+        };
+        let (mut token, mut rest) = next_token(rest).ok_or_else(invalid)?;
+        if !is_key_type(token) {
+            let mut options = token;
+            while !options.is_empty() {
+                let end = options
+                    .char_indices()
+                    .scan(false, |in_quotes, (i, c)| {
+                        if c == '"' {
+                            *in_quotes = !*in_quotes;
+                        }
+                        Some((i, c == ',' && !*in_quotes))
+                    })
+                    .find(|(_, is_separator)| *is_separator)
+                    .map_or(options.len(), |(i, _)| i);
+                let option = &options[..end];
+                options = options.get(end + 1..).unwrap_or("");
+                let (name, value) = match option.split_once('=') {
+                    Some((name, value)) => (name, Some(unquote(value))),
+                    None => (option, None),
+                };
+                match (name.to_ascii_lowercase().as_str(), value) {
+                    ("cert-authority", None) => signer.cert_authority = true,
+                    ("namespaces", Some(value)) => signer.namespaces = Some(value.to_string()),
+                    ("valid-after", Some(value)) => {
+                        signer.valid_after = Some(parse_ssh_time(value)?)
+                    }
+                    ("valid-before", Some(value)) => {
+                        signer.valid_before = Some(parse_ssh_time(value)?)
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            (token, rest) = next_token(rest).ok_or_else(invalid)?;
+        }
+        let (encoded, _comment) = next_token(rest).ok_or_else(invalid)?;
+        if token == SSH_ED25519 {
+            signer.pk = Some(parse_openssh_public_key(token, encoded)?);
+        }
+        Ok(Some(signer))
+    }
+
+    pub fn parse_file<P>(path: P) -> Result<Vec<AllowedSigner>>
+    where
+        P: AsRef<Path>,
+    {
+        let s = platform::read_to_string(&path).map_err(|err| {
+            PError::new(
+                ErrorKind::Io,
+                format!(
+                    "could not read allowed signers file {}: {}",
+                    path.as_ref().display(),
+                    err
+                ),
+            )
+        })?;
+        let mut signers = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let signer = AllowedSigner::parse_line(line).map_err(|err| {
+                PError::new(
+                    ErrorKind::Encoding,
+                    format!("{}:{}: {}", path.as_ref().display(), i + 1, err),
+                )
+            })?;
+            signers.extend(signer);
+        }
+        Ok(signers)
+    }
+
+    fn accepts(&self, pk: &[u8; PUBLIC_KEY_BYTES], time: u64) -> bool {
+        !self.cert_authority
+            && self.pk.as_ref() == Some(pk)
+            && self
+                .valid_after
+                .is_none_or(|valid_after| time >= valid_after)
+            && self
+                .valid_before
+                .is_none_or(|valid_before| time <= valid_before)
+    }
+
+    fn allows_namespace(&self, namespace: &str) -> bool {
+        self.namespaces
+            .as_ref()
+            .is_none_or(|namespaces| match_pattern_list(namespaces, namespace))
+    }
+}
+
+fn is_revoked<P>(revocation_path: Option<P>, pk: &[u8; PUBLIC_KEY_BYTES]) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let revocation_path = match revocation_path {
+        None => return Ok(false),
+        Some(revocation_path) => revocation_path,
+    };
+    let s = platform::read_to_string(revocation_path)?;
+    Ok(s.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some(key_type), Some(encoded)) => {
+                parse_openssh_public_key(key_type, encoded).ok().as_ref() == Some(pk)
+            }
+            _ => false,
+        }
+    }))
+}
+
+fn load_ssh_secret_key<P>(key_path: P) -> Result<SecretKey>
+where
+    P: AsRef<Path>,
+{
+    load_secret_key(&key_path, &Passphrase::Empty)
+        .or_else(|_| load_secret_key(&key_path, &Passphrase::Prompt))
+}
+
+/// Print the public key of a minisign key file in OpenSSH format (`ssh-keygen -y`).
+pub fn cmd_ssh_public_key<P>(key_path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let pk = match PublicKey::from_file(&key_path) {
+        Ok(pk) => pk,
+        Err(_) => PublicKey::from_secret_key(&load_ssh_secret_key(&key_path)?)?,
+    };
+    openssh_public_key(&public_key_bytes(&pk), "")
+}
+
+/// Sign files (or the standard input) with a minisign secret key, producing
+/// SSHSIG signatures (`ssh-keygen -Y sign`).
+pub fn cmd_ssh_sign<P>(
+    key_path: P,
+    namespace: &str,
+    options: &SshKeygenOptions,
+    data_paths: &[PathBuf],
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let sk = load_ssh_secret_key(key_path)?;
+    let hash_alg = options.hash_alg.as_deref().unwrap_or(DEFAULT_HASH_ALG);
+    if data_paths.is_empty() || data_paths == [PathBuf::from("-")] {
+        let signature = SshSignature::sign(&sk, namespace, hash_alg, std::io::stdin())?;
+        std::io::stdout().write_all(signature.to_armored()?.as_bytes())?;
+        return Ok(());
+    }
+    for data_path in data_paths {
+        let signature_path = with_suffix(data_path, ".sig");
+        eprintln!("Signing file {}", data_path.display());
+        let data_reader = open_data_file(data_path)?;
+        let signature = SshSignature::sign(&sk, namespace, hash_alg, data_reader)?;
+        let mut signature_writer = create_sig_file(&signature_path)?;
+        signature_writer.write_all(signature.to_armored()?.as_bytes())?;
+        signature_writer.flush()?;
+        eprintln!("Write signature to {}", signature_path.display());
+    }
+    Ok(())
+}
+
+/// Verify an SSHSIG signature of the standard input, made by `principal`
+/// according to an `allowed_signers` file (`ssh-keygen -Y verify`).
+pub fn cmd_ssh_verify<P, Q, R>(
+    allowed_signers_path: P,
+    principal: &str,
+    namespace: &str,
+    signature_path: Q,
+    revocation_path: Option<R>,
+    options: &SshKeygenOptions,
+) -> Result<String>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    let signature = SshSignature::from_file(signature_path)?;
+    let time = options.verify_time.unwrap_or_else(unix_timestamp);
+    let signers = AllowedSigner::parse_file(allowed_signers_path)?;
+    if !signers.iter().any(|signer| {
+        signer.accepts(&signature.pk, time)
+            && signer.allows_namespace(namespace)
+            && match_pattern_list(&signer.principals, principal)
+    }) {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            format!("Signature key is not an allowed signer for {principal}"),
+        ));
+    }
+    if is_revoked(revocation_path, &signature.pk)? {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            "Signature key has been revoked",
+        ));
+    }
+    signature.verify(namespace, std::io::stdin())?;
+    Ok(format!(
+        "Good \"{}\" signature for {} with ED25519 key {}",
+        namespace,
+        principal,
+        fingerprint(&signature.pk)?
+    ))
+}
+
+/// List the principals allowed to use the key of a signature
+/// (`ssh-keygen -Y find-principals`).
+pub fn cmd_ssh_find_principals<P, Q>(
+    allowed_signers_path: P,
+    signature_path: Q,
+    options: &SshKeygenOptions,
+) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let signature = SshSignature::from_file(signature_path)?;
+    let time = options.verify_time.unwrap_or_else(unix_timestamp);
+    let principals: Vec<_> = AllowedSigner::parse_file(allowed_signers_path)?
+        .into_iter()
+        .filter(|signer| signer.accepts(&signature.pk, time))
+        .map(|signer| signer.principals)
+        .collect();
+    if principals.is_empty() {
+        return Err(PError::new(ErrorKind::Verify, "No principal matched"));
+    }
+    Ok(principals)
+}
+
+/// Check that an SSHSIG signature of the standard input is valid, without
+/// checking who made it (`ssh-keygen -Y check-novalidate`).
+pub fn cmd_ssh_check_novalidate<P>(namespace: &str, signature_path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let signature = SshSignature::from_file(signature_path)?;
+    signature.verify(namespace, std::io::stdin())?;
+    Ok(format!(
+        "Good \"{}\" signature with ED25519 key {}",
+        namespace,
+        fingerprint(&signature.pk)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sshsig_round_trip() {
+        let KeyPair { sk, .. } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let data = b"commit content";
+        let signature = SshSignature::sign(&sk, "git", "sha512", &data[..]).unwrap();
+        let armored = signature.to_armored().unwrap();
+        assert!(armored.starts_with(ARMOR_BEGIN));
+        let signature = SshSignature::from_armored(&armored).unwrap();
+        signature.verify("git", &data[..]).unwrap();
+        assert!(signature.verify("file", &data[..]).is_err());
+        assert!(signature.verify("git", &b"other content"[..]).is_err());
+    }
+
+    #[test]
+    fn test_pattern_list() {
+        assert!(match_pattern_list("*@example.com", "alice@example.com"));
+        assert!(match_pattern_list(
+            "bob,alice@example.co?",
+            "alice@example.com"
+        ));
+        assert!(!match_pattern_list(
+            "*@example.com,!eve@*",
+            "eve@example.com"
+        ));
+        assert!(!match_pattern_list("alice", "bob"));
+        assert!(match_pattern_list("a*b*c", "aXbYbZc"));
+        assert!(match_pattern_list("*", ""));
+        assert!(!match_pattern_list("a*?", "a"));
+        let pattern = "*a".repeat(30) + "b";
+        assert!(!match_pattern_list(&pattern, &"a".repeat(60)));
+    }
+
+    #[test]
+    fn test_allowed_signers_line() {
+        let pk = [7u8; PUBLIC_KEY_BYTES];
+        let key = openssh_public_key(&pk, "comment").unwrap();
+        let line = format!(
+            "\"alice@example.com,bob\" namespaces=\"git,file\",valid-after=\"20200101\" {key}"
+        );
+        let signer = AllowedSigner::parse_line(&line).unwrap().unwrap();
+        assert_eq!(signer.principals, "alice@example.com,bob");
+        assert!(signer.allows_namespace("git"));
+        assert!(!signer.allows_namespace("email"));
+        assert!(signer.accepts(&pk, 1_700_000_000));
+        assert!(!signer.accepts(&pk, 1_500_000_000));
+        assert!(AllowedSigner::parse_line("# comment").unwrap().is_none());
+        assert!(AllowedSigner::parse_line("alice unknown-option ssh-ed25519 AAAA").is_err());
+    }
+
+    #[test]
+    fn test_parse_ssh_time() {
+        use crate::platform::{with_platform, MemoryPlatform};
+
+        // UTC+2: local times are 2 hours ahead
+        with_platform(MemoryPlatform::new(0).with_utc_offset(7200), || {
+            assert_eq!(parse_ssh_time("20240229").unwrap(), 1_709_164_800 - 7200);
+            assert_eq!(
+                parse_ssh_time("202402291230").unwrap(),
+                1_709_209_800 - 7200
+            );
+            assert_eq!(parse_ssh_time("202402291230Z").unwrap(), 1_709_209_800);
+            assert_eq!(parse_ssh_time("20240229123015z").unwrap(), 1_709_209_815);
+            assert!(parse_ssh_time("2024").is_err());
+        });
+    }
+}
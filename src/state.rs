@@ -0,0 +1,254 @@
+use std::cmp::Ordering;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use minisign::*;
+use serde::{Deserialize, Serialize};
+
+use crate::comment;
+use crate::helpers::{create_file, format_utc, with_suffix};
+use crate::platform;
+use crate::raw::key_id;
+
+/// The trusted comment field holding the version of a signed artifact.
+pub const VERSION_FIELD: &str = "version";
+
+/// The newest signature accepted for an artifact with a given key.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateEntry {
+    pub key_id: String,
+    pub artifact: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Verifier state, used to reject signatures older than the ones that have
+/// already been accepted (rollback attacks).
+#[derive(Default, Serialize, Deserialize)]
+pub struct VerifierState {
+    #[serde(skip)]
+    path: PathBuf,
+    entries: Vec<StateEntry>,
+}
+
+/// Compare dotted versions such as `1.10.2` or `2.0.0-rc1`, numerically
+/// when both components are numbers.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split(['.', '-', '+']);
+    let mut b_parts = b.split(['.', '-', '+']);
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+                    (Ok(x), Ok(y)) => x.cmp(&y),
+                    _ => x.cmp(y),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+impl VerifierState {
+    /// Load a state file. A missing file is an empty state.
+    pub fn load<P>(path: P) -> Result<VerifierState>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut state = if platform::exists(path) {
+            let json = platform::read_to_string(path)?;
+            serde_json::from_str(&json).map_err(|e| {
+                PError::new(
+                    ErrorKind::Encoding,
+                    format!("invalid state file {}: {}", path.display(), e),
+                )
+            })?
+        } else {
+            VerifierState::default()
+        };
+        state.path = path.to_path_buf();
+        Ok(state)
+    }
+
+    /// Write the state to a temporary file, then atomically move it over
+    /// the previous state.
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| PError::new(ErrorKind::Encoding, e.to_string()))?;
+        let tmp_path = with_suffix(&self.path, format!(".tmp{}", std::process::id()));
+        let mut writer = create_file(&tmp_path, 0o644)?;
+        writer.write_all(json.as_bytes())?;
+        writer.write_all(b"\n")?;
+        writer
+            .into_inner()
+            .map_err(|e| PError::new(ErrorKind::Io, e.to_string()))?
+            .sync_all()?;
+        platform::rename(&tmp_path, &self.path).map_err(|e| {
+            let _ = platform::remove_file(&tmp_path);
+            PError::new(
+                ErrorKind::Io,
+                format!("could not update {}: {}", self.path.display(), e),
+            )
+        })
+    }
+
+    pub fn entries(&self) -> &[StateEntry] {
+        &self.entries
+    }
+
+    /// Reject a signature older than the newest one accepted for the same
+    /// key and artifact, and record it otherwise. Versions are compared if
+    /// both signatures have one, timestamps otherwise.
+    pub fn check_and_update(
+        &mut self,
+        keynum: &[u8],
+        artifact: &str,
+        trusted_comment: &str,
+    ) -> Result<()> {
+        let candidate = StateEntry {
+            key_id: key_id(keynum),
+            artifact: artifact.to_string(),
+            timestamp: comment::timestamp(trusted_comment),
+            version: comment::field(trusted_comment, VERSION_FIELD).map(str::to_string),
+        };
+        if candidate.timestamp.is_none() && candidate.version.is_none() {
+            return Err(PError::new(
+                ErrorKind::Verify,
+                "the signature has neither a version nor a timestamp, rollbacks can't be detected",
+            ));
+        }
+        let entry = match self
+            .entries
+            .iter_mut()
+            .find(|e| e.key_id == candidate.key_id && e.artifact == candidate.artifact)
+        {
+            Some(entry) => entry,
+            None => {
+                self.entries.push(candidate);
+                return Ok(());
+            }
+        };
+        let rollback = match (&candidate.version, &entry.version) {
+            (Some(version), Some(seen)) => (compare_versions(version, seen) == Ordering::Less)
+                .then(|| format!("version {version} is older than version {seen}")),
+            _ => match (candidate.timestamp, entry.timestamp) {
+                (Some(timestamp), Some(seen)) if timestamp < seen => Some(format!(
+                    "it was made on {}, before the signature accepted on {}",
+                    format_utc(timestamp),
+                    format_utc(seen)
+                )),
+                (None, Some(_)) => Some("it has no timestamp".to_string()),
+                _ => None,
+            },
+        };
+        if let Some(reason) = rollback {
+            return Err(PError::new(
+                ErrorKind::Verify,
+                format!("Possible rollback attack on {artifact}: {reason}"),
+            ));
+        }
+        if candidate.version.is_some() {
+            entry.version = candidate.version;
+        }
+        if candidate.timestamp > entry.timestamp {
+            entry.timestamp = candidate.timestamp;
+        }
+        Ok(())
+    }
+
+    /// Forget entries, optionally only those of a key or of an artifact.
+    /// Returns the number of removed entries.
+    pub fn reset(&mut self, key_id: Option<&str>, artifact: Option<&str>) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|e| {
+            !(key_id.is_none_or(|key_id| e.key_id.eq_ignore_ascii_case(key_id))
+                && artifact.is_none_or(|artifact| e.artifact == artifact))
+        });
+        before - self.entries.len()
+    }
+}
+
+pub fn cmd_state_show<P>(state_path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let state = VerifierState::load(state_path)?;
+    let mut out = String::new();
+    for entry in state.entries() {
+        out.push_str(&format!("{}\t{}", entry.key_id, entry.artifact));
+        if let Some(version) = &entry.version {
+            out.push_str(&format!("\tversion:{version}"));
+        }
+        if let Some(timestamp) = entry.timestamp {
+            out.push_str(&format!(
+                "\ttimestamp:{} ({})",
+                timestamp,
+                format_utc(timestamp)
+            ));
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+pub fn cmd_state_reset<P>(
+    state_path: P,
+    key_id: Option<&str>,
+    artifact: Option<&str>,
+) -> Result<usize>
+where
+    P: AsRef<Path>,
+{
+    let mut state = VerifierState::load(state_path)?;
+    let removed = state.reset(key_id, artifact);
+    state.save()?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.10.0", "1.9.3"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.0"), Ordering::Less);
+        assert_eq!(compare_versions("2.0.0", "2.0.0"), Ordering::Equal);
+        assert_eq!(
+            compare_versions("2.0.0-rc2", "2.0.0-rc1"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_rollback_detection() {
+        let keynum = [1u8; 8];
+        let mut state = VerifierState::default();
+        state
+            .check_and_update(&keynum, "app", "timestamp:2000\tversion:1.2.0")
+            .unwrap();
+        assert!(state
+            .check_and_update(&keynum, "app", "timestamp:3000\tversion:1.1.9")
+            .is_err());
+        state
+            .check_and_update(&keynum, "app", "timestamp:1000\tversion:1.2.0")
+            .unwrap();
+        state
+            .check_and_update(&keynum, "other", "timestamp:1000")
+            .unwrap();
+        assert!(state
+            .check_and_update(&keynum, "other", "timestamp:999")
+            .is_err());
+        assert_eq!(state.entries()[0].timestamp, Some(2000));
+        assert_eq!(state.reset(None, Some("other")), 1);
+        assert_eq!(state.entries().len(), 1);
+    }
+}
@@ -0,0 +1,624 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use minisign::*;
+use serde::Serialize;
+
+use crate::bundle::SignatureBundle;
+use crate::certificate::{Certificate, CERT_FIELD};
+use crate::comment;
+use crate::expiry::check_expiry;
+use crate::filename::{check_file_name, FileBinding};
+use crate::helpers::{is_printable, open_data_file, unix_timestamp};
+use crate::namespace::NamespacePolicy;
+use crate::policy::Policy;
+use crate::prehash::{prehash, verify_comment_signature, verify_prehashed, DataSource};
+use crate::raw::{key_id, public_key_bytes, PUBLIC_KEY_BYTES};
+use crate::state::VerifierState;
+
+/// Options of `cmd_verify()`.
+#[derive(Default)]
+pub struct VerifyOptions<'a> {
+    /// Number of trusted keys that must have signed, all of them by default.
+    pub threshold: Option<usize>,
+    /// Certificate of a subkey, for signatures without an embedded one.
+    pub certificate: Option<&'a Certificate>,
+    /// Purpose that certificates must allow.
+    pub purpose: Option<&'a str>,
+    pub namespaces: NamespacePolicy,
+    /// Time at which expiration is checked, the current time by default.
+    pub at: Option<u64>,
+    pub policy: Option<&'a Policy>,
+    /// Accept legacy (non-prehashed) signatures.
+    pub allow_legacy: bool,
+    /// Escape control characters in comments instead of refusing them.
+    pub escape_comment: bool,
+    /// How the `file:` field is compared with the verified file.
+    pub bind_filename: FileBinding,
+    /// Return the verified content of the data file in the report.
+    pub keep_data: bool,
+}
+
+/// A signature that was verified with a trusted key.
+#[derive(Clone, Debug, Serialize)]
+pub struct VerifiedSignature {
+    /// ID of the trusted key, which is the root key for certified subkeys.
+    pub key_id: String,
+    /// ID of the key that made the signature, which differs from `key_id`
+    /// for certified subkeys.
+    pub signing_key_id: String,
+    /// Whether the data was prehashed, as opposed to a legacy signature.
+    pub prehashed: bool,
+    /// The trusted comment, without the embedded certificate.
+    pub trusted_comment: String,
+    /// Fields of the trusted comment, such as `timestamp` and `file`.
+    pub fields: BTreeMap<String, String>,
+    pub untrusted_comment: String,
+}
+
+impl VerifiedSignature {
+    fn new(
+        trusted_key_id: String,
+        signature_box: &SignatureBox,
+        escape_comment: bool,
+    ) -> Result<Self> {
+        let escape = |comment: String| {
+            if escape_comment {
+                comment::escape(&comment)
+            } else {
+                comment
+            }
+        };
+        let trusted_comment = escape(comment::without_field(
+            &signature_box.trusted_comment()?,
+            CERT_FIELD,
+        ));
+        let fields = comment::fields(&trusted_comment)
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Ok(VerifiedSignature {
+            key_id: trusted_key_id,
+            signing_key_id: key_id(signature_box.keynum()),
+            prehashed: signature_box.is_prehashed(),
+            trusted_comment,
+            fields,
+            untrusted_comment: escape(signature_box.untrusted_comment()?),
+        })
+    }
+}
+
+/// Refuse trusted comments with control characters, which could fool the
+/// terminal they are displayed in, unless they are going to be escaped.
+fn check_printable(bundle: &SignatureBundle, escape_comment: bool) -> Result<()> {
+    if escape_comment {
+        return Ok(());
+    }
+    for signature_box in bundle.boxes() {
+        if !is_printable(&signature_box.trusted_comment()?) {
+            return Err(PError::new(
+                ErrorKind::Verify,
+                "Signature file contains unprintable characters, use --escape-comment to display them",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// What `cmd_verify()` checked: the signatures that were verified, and the
+/// number that was required.
+#[derive(Clone, Debug, Serialize)]
+pub struct VerificationReport {
+    pub signatures: Vec<VerifiedSignature>,
+    /// Countersignatures of the signature file, filled in by callers that
+    /// also ran `cmd_verify_countersignatures()`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub countersignatures: Vec<VerifiedSignature>,
+    pub trusted_keys: usize,
+    pub threshold: usize,
+    /// The content that was verified, with `keep_data`.
+    #[serde(skip)]
+    pub data: Option<Vec<u8>>,
+}
+
+/// The signed data, read once and shared by every signature that is checked
+/// against it.
+pub(crate) struct SignedData<'a> {
+    /// The complete content, required by legacy signatures.
+    content: Option<Vec<u8>>,
+    prehash: Option<Cow<'a, [u8]>>,
+}
+
+impl<'a> SignedData<'a> {
+    /// Read the data source, keeping the complete content if `keep_content`
+    /// is set and only its prehash otherwise.
+    pub(crate) fn load(data: &DataSource<'a>, keep_content: bool) -> Result<SignedData<'a>> {
+        match data {
+            DataSource::File(data_path) => {
+                let read_error = |err: PError| {
+                    PError::new(
+                        ErrorKind::Io,
+                        format!("could not read data file {}: {}", data_path.display(), err),
+                    )
+                };
+                let mut data_reader = open_data_file(data_path).map_err(read_error)?;
+                if keep_content {
+                    let mut content = vec![];
+                    data_reader
+                        .read_to_end(&mut content)
+                        .map_err(|err| read_error(err.into()))?;
+                    let prehash = prehash(&content[..])?;
+                    Ok(SignedData {
+                        content: Some(content),
+                        prehash: Some(Cow::Owned(prehash)),
+                    })
+                } else {
+                    let prehash = prehash(data_reader).map_err(read_error)?;
+                    Ok(SignedData {
+                        content: None,
+                        prehash: Some(Cow::Owned(prehash)),
+                    })
+                }
+            }
+            DataSource::Prehash(prehash) => Ok(SignedData {
+                content: None,
+                prehash: Some(Cow::Borrowed(*prehash)),
+            }),
+            DataSource::CommentOnly => Ok(SignedData {
+                content: None,
+                prehash: None,
+            }),
+        }
+    }
+}
+
+/// Whether some signatures cover the whole file rather than its prehash.
+fn has_legacy_signatures(bundle: &SignatureBundle) -> bool {
+    bundle
+        .boxes()
+        .iter()
+        .any(|signature_box| !signature_box.is_prehashed())
+}
+
+pub(crate) fn verify_signature_box(
+    pk: &PublicKey,
+    signature_box: &SignatureBox,
+    data: &SignedData<'_>,
+    allow_legacy: bool,
+) -> Result<()> {
+    match (&data.content, &data.prehash) {
+        (Some(content), _) => verify(
+            pk,
+            signature_box,
+            Cursor::new(content),
+            true,
+            false,
+            allow_legacy,
+        ),
+        (None, Some(prehash)) => verify_prehashed(pk, signature_box, prehash),
+        (None, None) => {
+            // Don't accept a signature that a complete verification would refuse
+            if !allow_legacy && !signature_box.is_prehashed() {
+                return Err(PError::new(
+                    ErrorKind::Verify,
+                    "Legacy signatures are not accepted",
+                ));
+            }
+            verify_comment_signature(pk, signature_box)
+        }
+    }
+}
+
+/// Verify a signature made by a subkey, using a certificate chain that
+/// leads to `root`, and enforce the constraints of every certificate.
+/// Returns the subkey.
+fn verify_certified_signature_box(
+    root: &PublicKey,
+    signature_box: &SignatureBox,
+    certificate: &Certificate,
+    data: &SignedData<'_>,
+    data_path: Option<&Path>,
+    purpose: Option<&str>,
+) -> Result<PublicKey> {
+    let (subkey, constraints) = certificate.verify_chain(root)?;
+    if subkey.keynum() != signature_box.keynum() {
+        return Err(PError::new(
+            ErrorKind::Verify,
+            format!(
+                "the certificate was issued for key {}, not for the signing key {}",
+                key_id(subkey.keynum()),
+                key_id(signature_box.keynum())
+            ),
+        ));
+    }
+    verify_signature_box(&subkey, signature_box, data, false)?;
+    let trusted_comment = signature_box.trusted_comment()?;
+    for constraints in &constraints {
+        constraints.check(&trusted_comment, data_path, purpose)?;
+    }
+    Ok(subkey)
+}
+
+/// A valid signature for one of the distinct trusted keys.
+struct Candidate<'a> {
+    /// Index of the trusted key in the list of distinct keys.
+    group: usize,
+    pk: &'a PublicKey,
+    /// Ed25519 key that made the signature, the trusted key or a subkey.
+    signer: [u8; PUBLIC_KEY_BYTES],
+    signature: VerifiedSignature,
+}
+
+/// Give a signing key to the trusted key `group`, taking it from another
+/// trusted key if that one can use a different signing key instead.
+fn assign_signer(
+    group: usize,
+    candidates: &[Candidate<'_>],
+    owners: &mut BTreeMap<[u8; PUBLIC_KEY_BYTES], usize>,
+    visited: &mut Vec<[u8; PUBLIC_KEY_BYTES]>,
+) -> bool {
+    for (index, candidate) in candidates.iter().enumerate() {
+        if candidate.group != group || visited.contains(&candidate.signer) {
+            continue;
+        }
+        visited.push(candidate.signer);
+        let available = match owners.get(&candidate.signer) {
+            Some(&owner) => assign_signer(candidates[owner].group, candidates, owners, visited),
+            None => true,
+        };
+        if available {
+            owners.insert(candidate.signer, index);
+            return true;
+        }
+    }
+    false
+}
+
+/// Check that every given key countersigned the signature file. Control
+/// characters in comments are refused, or escaped with `escape_comment`.
+pub fn cmd_verify_countersignatures<P, Q>(
+    pks: &[PublicKey],
+    signature_path: P,
+    countersignature_path: Q,
+    escape_comment: bool,
+) -> Result<Vec<VerifiedSignature>>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let countersignatures = SignatureBundle::from_file(&countersignature_path).map_err(|err| {
+        PError::new(
+            ErrorKind::Io,
+            format!(
+                "could not read countersignature file {}: {}",
+                countersignature_path.as_ref().display(),
+                err
+            ),
+        )
+    })?;
+    check_printable(&countersignatures, escape_comment)?;
+    let data = SignedData::load(
+        &DataSource::File(signature_path.as_ref()),
+        has_legacy_signatures(&countersignatures),
+    )?;
+    let mut verified = vec![];
+    for pk in pks {
+        let countersignature = countersignatures
+            .boxes()
+            .iter()
+            .find(|signature_box| signature_box.keynum() == pk.keynum())
+            .ok_or_else(|| {
+                PError::new(
+                    ErrorKind::Verify,
+                    format!("no countersignature by key {}", key_id(pk.keynum())),
+                )
+            })?;
+        verify_signature_box(pk, countersignature, &data, false).map_err(|err| {
+            PError::new(
+                ErrorKind::Verify,
+                format!("countersignature by key {}: {}", key_id(pk.keynum()), err),
+            )
+        })?;
+        verified.push(VerifiedSignature::new(
+            key_id(pk.keynum()),
+            countersignature,
+            escape_comment,
+        )?);
+    }
+    Ok(verified)
+}
+
+/// Verify a signature file against one or more trusted public keys.
+/// Verification succeeds if at least `threshold` distinct keys (all of them
+/// by default) produced a valid signature, either directly or through a
+/// subkey they certified; a key listed several times under different key
+/// IDs only counts once, and so does a signing key, even if several trusted
+/// keys certified it. Signatures must then name the verified file, if
+/// their trusted comment has a `file:` field, and satisfy the optional policy.
+/// Accepted signatures are recorded in the optional verifier state, under
+/// the given artifact name.
+pub fn cmd_verify<P>(
+    pks: &[PublicKey],
+    data: DataSource<'_>,
+    signature_path: P,
+    options: &VerifyOptions<'_>,
+    state: Option<(&mut VerifierState, &str)>,
+) -> Result<VerificationReport>
+where
+    P: AsRef<Path>,
+{
+    // Group the key IDs of the same Ed25519 key
+    let mut distinct_pks: Vec<Vec<&PublicKey>> = vec![];
+    for pk in pks {
+        match distinct_pks
+            .iter_mut()
+            .find(|group| public_key_bytes(group[0]) == public_key_bytes(pk))
+        {
+            Some(group) if group.iter().any(|x| x.keynum() == pk.keynum()) => {}
+            Some(group) => group.push(pk),
+            None => distinct_pks.push(vec![pk]),
+        }
+    }
+    let threshold = options.threshold.unwrap_or(distinct_pks.len());
+    if threshold == 0 || threshold > distinct_pks.len() {
+        return Err(PError::new(
+            ErrorKind::Misc,
+            format!(
+                "the threshold must be between 1 and the number of distinct public keys ({})",
+                distinct_pks.len()
+            ),
+        ));
+    }
+    let bundle = SignatureBundle::from_file(&signature_path).map_err(|err| {
+        PError::new(
+            ErrorKind::Io,
+            format!(
+                "could not read signature file {}: {}",
+                signature_path.as_ref().display(),
+                err
+            ),
+        )
+    })?;
+    check_printable(&bundle, options.escape_comment)?;
+    let data_source = data;
+    let data = SignedData::load(
+        &data_source,
+        options.keep_data || has_legacy_signatures(&bundle),
+    )?;
+
+    let at = options.at.unwrap_or_else(unix_timestamp);
+    let data_path = match data_source {
+        DataSource::File(data_path) => Some(data_path),
+        DataSource::Prehash(_) | DataSource::CommentOnly => None,
+    };
+    let policy = options.policy;
+    let allow_legacy =
+        options.allow_legacy || policy.is_some_and(|policy| policy.allows_legacy(data_path));
+    let mut candidates: Vec<Candidate<'_>> = vec![];
+    let mut last_error = None;
+    for (group, pk) in distinct_pks
+        .iter()
+        .enumerate()
+        .flat_map(|(group, pks)| pks.iter().map(move |pk| (group, *pk)))
+    {
+        let mut tried = false;
+        for signature_box in bundle.boxes() {
+            let trusted_comment = signature_box.trusted_comment()?;
+            let result = if signature_box.keynum() == pk.keynum() {
+                verify_signature_box(pk, signature_box, &data, allow_legacy).map(|()| pk.clone())
+            } else if let Some(encoded) = comment::field(&trusted_comment, CERT_FIELD) {
+                Certificate::decode(encoded).and_then(|certificate| {
+                    verify_certified_signature_box(
+                        pk,
+                        signature_box,
+                        &certificate,
+                        &data,
+                        data_path,
+                        options.purpose,
+                    )
+                })
+            } else if let Some(certificate) = options.certificate {
+                verify_certified_signature_box(
+                    pk,
+                    signature_box,
+                    certificate,
+                    &data,
+                    data_path,
+                    options.purpose,
+                )
+            } else {
+                continue;
+            };
+            tried = true;
+            let result = result.and_then(|signer| {
+                options.namespaces.check(pk, &trusted_comment)?;
+                check_expiry(&trusted_comment, at)?;
+                if let Some(data_path) = data_path {
+                    check_file_name(&trusted_comment, data_path, options.bind_filename)?;
+                }
+                if let Some(policy) = policy {
+                    policy.check(data_path, pk, &signer, signature_box, &trusted_comment, at)?;
+                }
+                Ok(signer)
+            });
+            match result {
+                Ok(signer) => {
+                    let signer = public_key_bytes(&signer);
+                    if !candidates
+                        .iter()
+                        .any(|x| x.group == group && x.signer == signer)
+                    {
+                        candidates.push(Candidate {
+                            group,
+                            pk,
+                            signer,
+                            signature: VerifiedSignature::new(
+                                key_id(pk.keynum()),
+                                signature_box,
+                                options.escape_comment,
+                            )?,
+                        });
+                    }
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+        if !tried {
+            // Report the key ID mismatch the same way a single signature would
+            last_error = verify_signature_box(pk, &bundle.boxes()[0], &data, allow_legacy).err();
+        }
+    }
+    // Each trusted key needs its own signing key
+    let mut owners = BTreeMap::new();
+    for group in 0..distinct_pks.len() {
+        assign_signer(group, &candidates, &mut owners, &mut vec![]);
+    }
+    let chosen: Vec<usize> = owners.into_values().collect();
+    let mut chosen: Vec<Candidate<'_>> = candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| chosen.contains(index))
+        .map(|(_, candidate)| candidate)
+        .collect();
+    chosen.sort_by_key(|candidate| candidate.group);
+    let verified: Vec<(&PublicKey, VerifiedSignature)> = chosen
+        .into_iter()
+        .map(|candidate| (candidate.pk, candidate.signature))
+        .collect();
+    if verified.len() < threshold {
+        return Err(match last_error {
+            Some(err) if distinct_pks.len() == 1 => err,
+            _ => PError::new(
+                ErrorKind::Verify,
+                format!(
+                    "only {} of the {} required signatures could be verified",
+                    verified.len(),
+                    threshold
+                ),
+            ),
+        });
+    }
+
+    if let Some((state, artifact)) = state {
+        for (pk, signature) in &verified {
+            state.check_and_update(pk.keynum(), artifact, &signature.trusted_comment)?;
+        }
+        state.save()?;
+    }
+
+    Ok(VerificationReport {
+        signatures: verified
+            .into_iter()
+            .map(|(_, signature)| signature)
+            .collect(),
+        countersignatures: vec![],
+        trusted_keys: distinct_pks.len(),
+        threshold,
+        data: data.content.filter(|_| options.keep_data),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verified_signature_fields() {
+        let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let signature_box = sign(
+            Some(&pk),
+            &sk,
+            &b"data"[..],
+            Some("timestamp:1556193335\tfile:a:b\tcert:AAAA"),
+            Some("untrusted"),
+        )
+        .unwrap();
+        let signature = VerifiedSignature::new(key_id(pk.keynum()), &signature_box, false).unwrap();
+        assert_eq!(signature.signing_key_id, signature.key_id);
+        assert!(signature.prehashed);
+        assert_eq!(signature.trusted_comment, "timestamp:1556193335\tfile:a:b");
+        assert_eq!(signature.fields["file"], "a:b");
+        assert!(!signature.fields.contains_key(CERT_FIELD));
+        assert_eq!(signature.untrusted_comment, "untrusted");
+    }
+
+    #[test]
+    fn test_duplicate_keys_and_kept_data() {
+        use crate::platform::{with_platform, MemoryPlatform};
+        use crate::raw::public_key_from_raw;
+
+        let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().unwrap();
+        let signature_box = sign(Some(&pk), &sk, &b"data"[..], Some("timestamp:0"), None).unwrap();
+        let platform = MemoryPlatform::new(0);
+        platform.write_file("/file", "data");
+        platform.write_file("/file.minisig", signature_box.into_string());
+        // The same key, imported under another key ID
+        let alias = public_key_from_raw(&[0xaa; 8], &public_key_bytes(&pk)).unwrap();
+        let pks = [alias, pk];
+        with_platform(platform, || {
+            let data = DataSource::File(Path::new("/file"));
+            let options = VerifyOptions {
+                threshold: Some(2),
+                ..Default::default()
+            };
+            assert!(cmd_verify(&pks, data, "/file.minisig", &options, None).is_err());
+            let options = VerifyOptions {
+                keep_data: true,
+                ..Default::default()
+            };
+            let report = cmd_verify(&pks, data, "/file.minisig", &options, None).unwrap();
+            assert_eq!(report.trusted_keys, 1);
+            assert_eq!(report.signatures.len(), 1);
+            assert_eq!(report.data.as_deref(), Some(&b"data"[..]));
+        });
+    }
+
+    #[test]
+    fn test_certified_signer_counts_once() {
+        use crate::certificate::Constraints;
+        use crate::platform::{with_platform, MemoryPlatform};
+
+        let a = KeyPair::generate_unencrypted_keypair().unwrap();
+        let b = KeyPair::generate_unencrypted_keypair().unwrap();
+        let cert = Certificate::issue(&b.pk, &b.sk, None, &a.pk, &Constraints::default()).unwrap();
+        let trusted_comment = format!("timestamp:0\t{}:{}", CERT_FIELD, cert.encode().unwrap());
+        let by_a = sign(
+            Some(&a.pk),
+            &a.sk,
+            &b"data"[..],
+            Some(&trusted_comment),
+            None,
+        )
+        .unwrap();
+        let by_b = sign(Some(&b.pk), &b.sk, &b"data"[..], Some("timestamp:0"), None).unwrap();
+        let mut bundle = SignatureBundle::default();
+        bundle.push(by_a).unwrap();
+        let platform = MemoryPlatform::new(0);
+        platform.write_file("/file", "data");
+        platform.write_file("/file.minisig", bundle.to_bytes());
+        bundle.push(by_b).unwrap();
+        platform.write_file("/both.minisig", bundle.to_bytes());
+        let pks = [a.pk.clone(), b.pk.clone()];
+        with_platform(platform, || {
+            let data = DataSource::File(Path::new("/file"));
+            let options = VerifyOptions::default();
+            // A signed, which is also valid for B through the certificate
+            let report = cmd_verify(&pks, data, "/file.minisig", &options, None);
+            assert!(report.is_err());
+            let options = VerifyOptions {
+                threshold: Some(1),
+                ..Default::default()
+            };
+            let report = cmd_verify(&pks, data, "/file.minisig", &options, None).unwrap();
+            assert_eq!(report.signatures.len(), 1);
+            // Whichever key is tried first, B can use its own signature
+            let options = VerifyOptions::default();
+            let report = cmd_verify(&pks, data, "/both.minisig", &options, None).unwrap();
+            assert_eq!(report.signatures.len(), 2);
+            let pks = [b.pk, a.pk];
+            let report = cmd_verify(&pks, data, "/both.minisig", &options, None).unwrap();
+            assert_eq!(report.signatures.len(), 2);
+        });
+    }
+}
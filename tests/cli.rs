@@ -0,0 +1,649 @@
+//! End-to-end tests of the `rsign` binary, run in temporary directories.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ct_codecs::{Base64, Decoder};
+use rsign2::minisign::{self, KeyPair, SIG_DEFAULT_CONFIG_DIR_ENV_VAR};
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/minisign");
+
+/// A temporary directory, used as the current and home directory of the
+/// commands it runs, and removed once the test is over.
+struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    fn new(name: &str) -> TestDir {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "rsign-cli-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    fn write(&self, name: &str, content: &str) -> PathBuf {
+        let path = self.path(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rsign"));
+        command
+            .args(args)
+            .current_dir(&self.path)
+            .env("HOME", &self.path)
+            .env("XDG_CONFIG_HOME", self.path.join(".config"))
+            .env_remove(SIG_DEFAULT_CONFIG_DIR_ENV_VAR);
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Run a command that must succeed, and return its standard error.
+    fn ok(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(output.status.success(), "rsign {:?}: {:?}", args, output);
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    /// Run a command that must fail, and return its standard error.
+    fn fails(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(!output.status.success(), "rsign {:?}: {:?}", args, output);
+        String::from_utf8(output.stderr).unwrap()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+fn copy_fixture(dir: &TestDir, name: &str) -> PathBuf {
+    let path = dir.path(name);
+    fs::copy(Path::new(FIXTURES_DIR).join(name), &path).unwrap();
+    path
+}
+
+#[test]
+fn passwordless_round_trip() {
+    let dir = TestDir::new("passwordless");
+    dir.ok(&["generate", "-W", "-p", "key.pub", "-s", "key.sec"]);
+    assert!(fs::read_to_string(dir.path("key.sec"))
+        .unwrap()
+        .starts_with("untrusted comment: "));
+    dir.write("file", "some data\n");
+    dir.ok(&["sign", "-W", "-s", "key.sec", "-t", "release 1", "file"]);
+    let stderr = dir.ok(&["verify", "-p", "key.pub", "file"]);
+    assert!(stderr.contains("Trusted comment: release 1"), "{}", stderr);
+
+    dir.write("file", "other data\n");
+    dir.fails(&["verify", "-p", "key.pub", "file"]);
+}
+
+#[test]
+fn unencrypted_round_trip() {
+    let dir = TestDir::new("unencrypted");
+    dir.ok(&[
+        "generate",
+        "--unencrypted",
+        "-p",
+        "key.pub",
+        "-s",
+        "key.sec",
+    ]);
+    dir.write("file", "some data\n");
+    // No password is needed, not even an empty one
+    dir.ok(&["sign", "-s", "key.sec", "file"]);
+    dir.ok(&["verify", "-p", "key.pub", "file"]);
+}
+
+#[test]
+fn encrypted_round_trip() {
+    let dir = TestDir::new("encrypted");
+    let mut pk_box = vec![];
+    let mut sk_box = vec![];
+    KeyPair::generate_and_write_encrypted_keypair(
+        &mut pk_box,
+        &mut sk_box,
+        None,
+        Some("correct horse".to_string()),
+    )
+    .unwrap();
+    fs::write(dir.path("key.pub"), pk_box).unwrap();
+    fs::write(dir.path("key.sec"), sk_box).unwrap();
+    fs::create_dir_all(dir.path(".config/rsign")).unwrap();
+    dir.write(
+        ".config/rsign/config.toml",
+        "passphrase = \"env:RSIGN_TEST_PASSWORD\"\n",
+    );
+    dir.write("file", "some data\n");
+
+    let wrong = dir
+        .command(&["sign", "-s", "key.sec", "file"])
+        .env("RSIGN_TEST_PASSWORD", "battery staple")
+        .output()
+        .unwrap();
+    assert!(!wrong.status.success());
+    assert!(!dir.path("file.minisig").exists());
+
+    let right = dir
+        .command(&["sign", "-s", "key.sec", "file"])
+        .env("RSIGN_TEST_PASSWORD", "correct horse")
+        .output()
+        .unwrap();
+    assert!(right.status.success(), "{:?}", right);
+    dir.ok(&["verify", "-p", "key.pub", "file"]);
+}
+
+#[test]
+fn control_characters_in_comments() {
+    let dir = TestDir::new("comments");
+    dir.ok(&[
+        "generate",
+        "--unencrypted",
+        "-p",
+        "key.pub",
+        "-s",
+        "key.sec",
+    ]);
+    dir.write("file", "some data\n");
+    let sign = ["sign", "-s", "key.sec", "-t", "a\x1b[2Jb", "file"];
+    let stderr = dir.fails(&sign);
+    assert!(stderr.contains("control character"), "{}", stderr);
+    assert!(!dir.path("file.minisig").exists());
+    dir.ok(&[&sign[..], &["--sanitize-comment", "escape"]].concat());
+    let stderr = dir.ok(&["verify", "-p", "key.pub", "file"]);
+    assert!(
+        stderr.contains("Trusted comment: a\\x1b[2Jb\n"),
+        "{}",
+        stderr
+    );
+
+    // Signatures made without the check are refused, unless escaped
+    let key_pair = KeyPair::generate_unencrypted_keypair().unwrap();
+    fs::write(
+        dir.path("hostile.pub"),
+        key_pair.pk.to_box().unwrap().to_string(),
+    )
+    .unwrap();
+    let signature_box = minisign::sign(
+        None,
+        &key_pair.sk,
+        &b"some data\n"[..],
+        Some("a\x1b[2Jb"),
+        None,
+    )
+    .unwrap();
+    fs::write(dir.path("file.minisig"), signature_box.to_string()).unwrap();
+    let stderr = dir.fails(&["verify", "-p", "hostile.pub", "file"]);
+    assert!(stderr.contains("unprintable characters"), "{}", stderr);
+    let stderr = dir.ok(&["verify", "-p", "hostile.pub", "--escape-comment", "file"]);
+    assert!(
+        stderr.contains("Trusted comment: a\\x1b[2Jb\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn whitespace_around_trusted_comments() {
+    let dir = TestDir::new("comment-whitespace");
+    dir.ok(&[
+        "generate",
+        "--unencrypted",
+        "-p",
+        "key.pub",
+        "-s",
+        "key.sec",
+    ]);
+    dir.write("file", "some data\n");
+    for (i, &trusted_comment) in ["release 1 ", "\tlead"].iter().enumerate() {
+        let sig_file = format!("file{i}.minisig");
+        let sign = [
+            "sign",
+            "-s",
+            "key.sec",
+            "-x",
+            &sig_file,
+            "-t",
+            trusted_comment,
+            "file",
+        ];
+        let stderr = dir.fails(&sign);
+        assert!(stderr.contains("whitespace"), "{}", stderr);
+        for mode in ["escape", "strip"] {
+            dir.ok(&[&sign[..], &["--sanitize-comment", mode]].concat());
+            let stderr = dir.ok(&["verify", "-p", "key.pub", "-x", &sig_file, "file"]);
+            assert!(
+                stderr.contains(&format!("Trusted comment: {}\n", trusted_comment.trim())),
+                "{}",
+                stderr
+            );
+            fs::remove_file(dir.path(&sig_file)).unwrap();
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_file_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = TestDir::new("latin1");
+    dir.ok(&[
+        "generate",
+        "--unencrypted",
+        "-p",
+        "key.pub",
+        "-s",
+        "key.sec",
+    ]);
+    let name = OsStr::from_bytes(b"caf\xe9.txt");
+    fs::write(dir.path.join(name), "some data\n").unwrap();
+    let output = dir
+        .command(&["sign", "-s", "key.sec"])
+        .arg(name)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(dir
+        .path
+        .join(OsStr::from_bytes(b"caf\xe9.txt.minisig"))
+        .exists());
+    let output = dir
+        .command(&["verify", "-p", "key.pub"])
+        .arg(name)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("\tfile:caf\\xe9.txt\t"), "{}", stderr);
+}
+
+#[test]
+fn renamed_signature_is_rejected() {
+    let dir = TestDir::new("bind-filename");
+    dir.ok(&[
+        "generate",
+        "--unencrypted",
+        "-p",
+        "key.pub",
+        "-s",
+        "key.sec",
+    ]);
+    dir.write("good.tar.gz", "some data\n");
+    dir.ok(&["sign", "-s", "key.sec", "good.tar.gz"]);
+    // Same content, but another name
+    dir.write("evil.tar.gz", "some data\n");
+    fs::copy(
+        dir.path("good.tar.gz.minisig"),
+        dir.path("evil.tar.gz.minisig"),
+    )
+    .unwrap();
+    let stderr = dir.fails(&["verify", "-p", "key.pub", "evil.tar.gz"]);
+    assert!(
+        stderr.contains("file:good.tar.gz, not evil.tar.gz"),
+        "{}",
+        stderr
+    );
+    dir.ok(&[
+        "verify",
+        "-p",
+        "key.pub",
+        "--bind-filename",
+        "off",
+        "evil.tar.gz",
+    ]);
+
+    fs::create_dir(dir.path("mirror")).unwrap();
+    fs::copy(dir.path("good.tar.gz"), dir.path("mirror/good.tar.gz")).unwrap();
+    let args = ["verify", "-p", "key.pub", "-x", "good.tar.gz.minisig"];
+    dir.ok(&[&args[..], &["mirror/good.tar.gz"]].concat());
+    dir.fails(
+        &[
+            &args[..],
+            &["--bind-filename", "path", "mirror/good.tar.gz"],
+        ]
+        .concat(),
+    );
+
+    // A project file can't turn the binding off, the user configuration can
+    dir.write(".rsign.toml", "bind_filename = \"off\"\n");
+    dir.fails(&["verify", "-p", "key.pub", "evil.tar.gz"]);
+    let stdout = dir.run(&["config", "show"]).stdout;
+    assert!(String::from_utf8(stdout)
+        .unwrap()
+        .contains("# bind_filename is ignored in"),);
+    fs::create_dir_all(dir.path(".config/rsign")).unwrap();
+    dir.write(".config/rsign/config.toml", "bind_filename = \"off\"\n");
+    dir.ok(&["verify", "-p", "key.pub", "evil.tar.gz"]);
+}
+
+#[test]
+fn comment_only_verification() {
+    let dir = TestDir::new("comment-only");
+    copy_fixture(&dir, "minisign.pub");
+    let sig = copy_fixture(&dir, "test.minisig");
+    // The data file is not needed
+    let stderr = dir.ok(&[
+        "verify",
+        "-p",
+        "minisign.pub",
+        "--comment-only",
+        "test.minisig",
+    ]);
+    assert!(stderr.contains("Comment signature verified"), "{}", stderr);
+    assert!(
+        stderr.contains("Trusted comment: timestamp:1556193335\tfile:test"),
+        "{}",
+        stderr
+    );
+
+    let tampered = fs::read_to_string(&sig)
+        .unwrap()
+        .replace("timestamp:1556193335", "timestamp:1556193336");
+    fs::write(&sig, tampered).unwrap();
+    let stderr = dir.fails(&[
+        "verify",
+        "-p",
+        "minisign.pub",
+        "--comment-only",
+        "test.minisig",
+    ]);
+    assert!(
+        stderr.contains("Comment signature verification failed"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn sig_check_and_normalize() {
+    let dir = TestDir::new("sig-normalize");
+    copy_fixture(&dir, "minisign.pub");
+    copy_fixture(&dir, "test");
+    let sig = copy_fixture(&dir, "test.minisig");
+    let original = fs::read_to_string(&sig).unwrap();
+    dir.ok(&["sig", "check", "test.minisig"]);
+
+    // What a Windows editor might do to the file
+    fs::write(
+        &sig,
+        format!("\u{feff}{}\r\n", original.replace('\n', " \r\n")),
+    )
+    .unwrap();
+    let output = dir.run(&["sig", "check", "test.minisig"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("line 1: byte order mark"), "{}", stdout);
+    assert!(stdout.contains("CRLF line endings"), "{}", stdout);
+
+    let stderr = dir.ok(&["sig", "normalize", "test.minisig"]);
+    assert!(stderr.contains("Normalized test.minisig"), "{}", stderr);
+    assert_eq!(fs::read_to_string(&sig).unwrap(), original);
+    let stderr = dir.ok(&["sig", "normalize", "test.minisig"]);
+    assert!(stderr.is_empty(), "{}", stderr);
+
+    dir.ok(&[
+        "sig",
+        "set-untrusted-comment",
+        "test.minisig",
+        "release 1.0",
+    ]);
+    assert!(fs::read_to_string(&sig)
+        .unwrap()
+        .starts_with("untrusted comment: release 1.0\n"));
+    let stderr = dir.ok(&["verify", "-p", "minisign.pub", "test"]);
+    assert!(
+        stderr.contains("Signature and comment signature verified"),
+        "{}",
+        stderr
+    );
+    dir.fails(&[
+        "sig",
+        "set-untrusted-comment",
+        "--key-id",
+        "0000000000000000",
+        "test.minisig",
+        "other",
+    ]);
+}
+
+#[test]
+fn signify_format_refuses_minisign_checks() {
+    let dir = TestDir::new("signify-checks");
+    for check in [
+        &["--namespace", "release"][..],
+        &["--threshold", "1"],
+        &["--purpose", "release"],
+        &["--at", "20260101"],
+        &["--bind-filename", "path"],
+        &["--escape-comment"],
+    ] {
+        let mut args = vec!["verify", "--format", "signify", "-p", "key.pub", "file"];
+        args.extend_from_slice(check);
+        let stderr = dir.fails(&args);
+        assert!(stderr.contains("cannot be used with"), "{}", stderr);
+    }
+}
+
+#[test]
+fn append_requires_matching_signatures() {
+    let dir = TestDir::new("append");
+    dir.ok(&["generate", "-W", "-p", "key.pub", "-s", "key.sec"]);
+    dir.write("file", "some data\n");
+    dir.ok(&["sign", "-W", "-s", "key.sec", "file"]);
+    dir.write("file", "other data\n");
+    let stderr = dir.fails(&["sign", "-W", "-s", "key.sec", "--append", "file"]);
+    assert!(stderr.contains("doesn't match the data"), "{}", stderr);
+    dir.write("file", "some data\n");
+    dir.ok(&["generate", "-W", "-p", "key2.pub", "-s", "key2.sec"]);
+    dir.ok(&["sign", "-W", "-s", "key2.sec", "--append", "file"]);
+
+    copy_fixture(&dir, "test");
+    fs::copy(
+        Path::new(FIXTURES_DIR).join("test.legacy.minisig"),
+        dir.path("test.minisig"),
+    )
+    .unwrap();
+    let stderr = dir.fails(&["sign", "-W", "-s", "key.sec", "--append", "test"]);
+    assert!(stderr.contains("legacy signatures"), "{}", stderr);
+}
+
+#[test]
+fn generate_requires_force_to_overwrite() {
+    let dir = TestDir::new("force");
+    dir.ok(&[
+        "generate",
+        "--unencrypted",
+        "-p",
+        "key.pub",
+        "-s",
+        "key.sec",
+    ]);
+    let pk = fs::read_to_string(dir.path("key.pub")).unwrap();
+    let stderr = dir.fails(&[
+        "generate",
+        "--unencrypted",
+        "-p",
+        "key.pub",
+        "-s",
+        "key.sec",
+    ]);
+    assert!(stderr.contains("already exists"), "{}", stderr);
+    assert_eq!(fs::read_to_string(dir.path("key.pub")).unwrap(), pk);
+
+    dir.ok(&[
+        "generate",
+        "--unencrypted",
+        "-f",
+        "-p",
+        "key.pub",
+        "-s",
+        "key.sec",
+    ]);
+    assert_ne!(fs::read_to_string(dir.path("key.pub")).unwrap(), pk);
+    dir.write("file", "some data\n");
+    dir.ok(&["sign", "-s", "key.sec", "file"]);
+    dir.ok(&["verify", "-p", "key.pub", "file"]);
+}
+
+#[test]
+fn default_paths_from_config_dir_env_var() {
+    let dir = TestDir::new("config-dir");
+    let keys_dir = dir.path("keys");
+    let keys_dir = keys_dir.to_str().unwrap();
+
+    let missing = dir
+        .command(&["generate", "--unencrypted"])
+        .env(SIG_DEFAULT_CONFIG_DIR_ENV_VAR, keys_dir)
+        .output()
+        .unwrap();
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("doesn't exist"));
+
+    fs::create_dir(keys_dir).unwrap();
+    let generated = dir
+        .command(&["generate", "--unencrypted"])
+        .env(SIG_DEFAULT_CONFIG_DIR_ENV_VAR, keys_dir)
+        .output()
+        .unwrap();
+    assert!(generated.status.success(), "{:?}", generated);
+    assert!(dir.path("keys/rsign.key").exists());
+    assert!(dir.path("rsign.pub").exists());
+
+    dir.write("file", "some data\n");
+    let signed = dir
+        .command(&["sign", "file"])
+        .env(SIG_DEFAULT_CONFIG_DIR_ENV_VAR, keys_dir)
+        .output()
+        .unwrap();
+    assert!(signed.status.success(), "{:?}", signed);
+    dir.ok(&["verify", "file"]);
+}
+
+#[test]
+fn minisign_prehashed_fixture() {
+    let dir = TestDir::new("fixture");
+    copy_fixture(&dir, "minisign.pub");
+    copy_fixture(&dir, "test");
+    copy_fixture(&dir, "test.minisig");
+    let stderr = dir.ok(&["verify", "-p", "minisign.pub", "test"]);
+    assert!(
+        stderr.contains("Trusted comment: timestamp:1556193335\tfile:test"),
+        "{}",
+        stderr
+    );
+
+    dir.write("test", "tset");
+    dir.fails(&["verify", "-p", "minisign.pub", "test"]);
+}
+
+#[test]
+fn minisign_legacy_fixture() {
+    let dir = TestDir::new("legacy");
+    copy_fixture(&dir, "minisign.pub");
+    copy_fixture(&dir, "test");
+    copy_fixture(&dir, "test.legacy.minisig");
+    let args = ["verify", "-p", "minisign.pub", "-x", "test.legacy.minisig"];
+    let stderr = dir.fails(&[&args[..], &["test"]].concat());
+    assert!(
+        stderr.contains("Legacy signatures are not accepted"),
+        "{}",
+        stderr
+    );
+    let stderr = dir.ok(&[&args[..], &["--allow-legacy", "test"]].concat());
+    assert!(
+        stderr.contains("Trusted comment: timestamp:1555779966\tfile:test"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn minisign_compat_mode_matches_fixtures() {
+    let dir = TestDir::new("compat");
+    copy_fixture(&dir, "minisign.pub");
+    copy_fixture(&dir, "test");
+    copy_fixture(&dir, "test.minisig");
+    copy_fixture(&dir, "test.legacy.minisig");
+    dir.ok(&["compat", "-V", "-p", "minisign.pub", "-m", "test"]);
+    // minisign accepts legacy signatures unless -H is given
+    dir.ok(&[
+        "compat",
+        "-V",
+        "-p",
+        "minisign.pub",
+        "-x",
+        "test.legacy.minisig",
+        "-m",
+        "test",
+    ]);
+    dir.fails(&[
+        "compat",
+        "-V",
+        "-H",
+        "-p",
+        "minisign.pub",
+        "-x",
+        "test.legacy.minisig",
+        "-m",
+        "test",
+    ]);
+}
+
+#[test]
+fn minisign_compat_mode_streams_and_unencrypted_keys() {
+    let dir = TestDir::new("compat-streams");
+    copy_fixture(&dir, "minisign.pub");
+    copy_fixture(&dir, "test");
+    copy_fixture(&dir, "test.minisig");
+    let args = ["compat", "-V", "-p", "minisign.pub", "-m", "test"];
+    let output = dir.run(&args);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("Signature and comment signature verified\nTrusted comment: "),
+        "{}",
+        stdout
+    );
+    assert!(output.stderr.is_empty());
+    // With -o, the standard output only has the data
+    let output = dir.run(&[&args[..], &["-o"]].concat());
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"test");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Trusted comment: "));
+    let output = dir.run(&[&args[..], &["-q"]].concat());
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    dir.ok(&["compat", "-G", "-W", "-p", "key.pub", "-s", "key.sec"]);
+    let sk = fs::read_to_string(dir.path("key.sec")).unwrap();
+    let encoded = sk.lines().nth(1).unwrap();
+    let sk_bytes = Base64::decode_to_vec(encoded, None).unwrap();
+    // No KDF: the algorithm follows the 2 bytes of the signature algorithm
+    assert_eq!(sk_bytes[2..4], [0, 0]);
+    minisign::SecretKey::from_unencrypted_box(minisign::SecretKeyBox::from_string(&sk).unwrap())
+        .unwrap();
+}